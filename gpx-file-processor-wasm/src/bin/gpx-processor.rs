@@ -0,0 +1,291 @@
+//! GPX Processor CLI
+//!
+//! Command-line front end to the same processing code the website runs in
+//! WebAssembly, for reprocessing archived rides in bulk:
+//! - `analyze` prints the analysis of each file as one JSON object per line
+//! - `reduce` writes the reduced JSON payload for each file
+//! - `compress` writes the compressed upload payload for each file
+//!
+//! Paths may be files or directories; directories are searched recursively
+//! for `.gpx`, `.gpx.gz`, `.fit` and `.fit.gz` files.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use gpx_file_processor_wasm::{
+    process_upload, reduce_upload_with_options, AnalysisOptions, CompressionCodec, CompressionOptions,
+    GpxProcessorError, PayloadFormat, ReduceOptions, SimplifyMode,
+};
+
+const USAGE: &str = "\
+Batch-process GPX and FIT files with the Michigan Bike Maps pipeline
+
+USAGE:
+    gpx-processor <COMMAND> [OPTIONS] <PATH>...
+
+COMMANDS:
+    analyze     Print the analysis of each file as one JSON object per line
+    reduce      Write the reduced JSON payload as <name>.reduced.json
+    compress    Write the compressed upload payload as <name>.smlr.json.gz
+                (<name>.smlr.bin.gz with --format binary; .mbc instead of .gz
+                with a codec other than gzip)
+
+OPTIONS:
+    -o, --out-dir <DIR>             Write output files here instead of next to each input
+        --decimals <N>              Coordinate decimal places (default 5)
+        --precision-m <M>           Coordinate precision in metres, instead of --decimals
+        --elevation-decimals <N>    Elevation decimal places, 0-3 (default 1)
+        --simplify <MODE>           none, dp (Douglas-Peucker) or vw (Visvalingam-Whyatt)
+        --tolerance-m <M>           Douglas-Peucker tolerance in metres (default 2)
+        --max-points <N>            Visvalingam-Whyatt point budget per track (default 5000)
+        --fill-elevation            Interpolate missing elevations from neighbouring points
+        --grade                     Add a smoothed grade percentage to each reduced point
+        --keep-times                Keep timestamps as second offsets from each segment's start
+        --no-metadata               Leave the name, description and other metadata out of the payload
+        --format <FORMAT>           Payload encoding: json (default) or binary
+        --codec <CODEC>             Payload compression: gzip (default), deflate, zlib or brotli
+        --level <N>                 Compression level, 0-9 (0-11 for brotli; default 6)
+        --moving-speed <M/S>        Slowest speed that counts as moving time (default 0.5)
+        --hysteresis-m <M>          Smallest elevation change counted toward gain/loss (default 3)
+        --min-climb-gain-m <M>      Smallest gain for a reported climb (default 15)
+        --min-climb-grade <PCT>     Shallowest average grade for a reported climb (default 3)
+        --profile-samples <N>       Include an elevation profile with N samples in the analysis
+    -h, --help                      Print this help
+";
+
+/// What to do with each input file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Analyze,
+    Reduce,
+    Compress,
+}
+
+/// Parsed command-line arguments.
+struct Args {
+    command: Command,
+    options: ReduceOptions,
+    analysis_options: AnalysisOptions,
+    out_dir: Option<PathBuf>,
+    paths: Vec<PathBuf>,
+}
+
+/// Parses an option value, naming the flag in the error.
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} requires a value", flag))?;
+    value.parse().map_err(|_| format!("invalid value for {}: {}", flag, value))
+}
+
+/// Parses the process arguments (without the program name).
+fn parse_args(mut raw: impl Iterator<Item = String>) -> Result<Args, String> {
+    let command = match raw.next().as_deref() {
+        Some("analyze") => Command::Analyze,
+        Some("reduce") => Command::Reduce,
+        Some("compress") => Command::Compress,
+        Some("-h" | "--help") | None => return Err(String::new()),
+        Some(other) => return Err(format!("unknown command: {}", other)),
+    };
+
+    let mut options = ReduceOptions::new();
+    let mut analysis_options = AnalysisOptions::new();
+    let mut compression = CompressionOptions::new();
+    let mut out_dir = None;
+    let mut paths = Vec::new();
+
+    while let Some(arg) = raw.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Err(String::new()),
+            "-o" | "--out-dir" => out_dir = Some(PathBuf::from(parse_value::<String>(&arg, raw.next())?)),
+            "--decimals" => options.set_coordinate_decimals(parse_value(&arg, raw.next())?),
+            "--precision-m" => options.set_coordinate_precision_metres(parse_value(&arg, raw.next())?),
+            "--elevation-decimals" => options.set_elevation_decimals(parse_value(&arg, raw.next())?),
+            "--tolerance-m" => options.set_simplify_tolerance_metres(parse_value(&arg, raw.next())?),
+            "--max-points" => options.set_max_points_per_track(parse_value(&arg, raw.next())?),
+            "--fill-elevation" => options.set_fill_elevation_gaps(true),
+            "--grade" => options.set_include_grade(true),
+            "--keep-times" => options.set_keep_times(true),
+            "--no-metadata" => options.set_keep_metadata(false),
+            "--format" => options.set_payload_format(match parse_value::<String>(&arg, raw.next())?.as_str() {
+                "json" => PayloadFormat::Json,
+                "binary" => PayloadFormat::Binary,
+                other => return Err(format!("unknown payload format: {}", other)),
+            }),
+            "--codec" => compression.set_codec(match parse_value::<String>(&arg, raw.next())?.as_str() {
+                "gzip" => CompressionCodec::Gzip,
+                "deflate" => CompressionCodec::Deflate,
+                "zlib" => CompressionCodec::Zlib,
+                "brotli" => CompressionCodec::Brotli,
+                other => return Err(format!("unknown codec: {}", other)),
+            }),
+            "--level" => compression.set_level(parse_value(&arg, raw.next())?),
+            "--moving-speed" => analysis_options.set_moving_speed_threshold_mps(parse_value(&arg, raw.next())?),
+            "--hysteresis-m" => analysis_options.set_elevation_hysteresis_m(parse_value(&arg, raw.next())?),
+            "--min-climb-gain-m" => analysis_options.set_min_climb_gain_m(parse_value(&arg, raw.next())?),
+            "--min-climb-grade" => analysis_options.set_min_climb_grade_pct(parse_value(&arg, raw.next())?),
+            "--profile-samples" => analysis_options.set_profile_samples(parse_value(&arg, raw.next())?),
+            "--simplify" => options.set_simplify_mode(match parse_value::<String>(&arg, raw.next())?.as_str() {
+                "none" => SimplifyMode::None,
+                "dp" => SimplifyMode::DouglasPeucker,
+                "vw" => SimplifyMode::VisvalingamWhyatt,
+                other => return Err(format!("unknown simplify mode: {}", other)),
+            }),
+            flag if flag.starts_with('-') => return Err(format!("unknown option: {}", flag)),
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    if paths.is_empty() {
+        return Err("no input paths given".to_string());
+    }
+    options.set_compression_options(&compression);
+
+    Ok(Args { command, options, analysis_options, out_dir, paths })
+}
+
+/// Returns true for `.gpx`, `.gpx.gz`, `.fit` and `.fit.gz` file names, ignoring case.
+fn is_gpx_file(path: &Path) -> bool {
+    let name = path.file_name().map(|name| name.to_string_lossy().to_lowercase()).unwrap_or_default();
+    [".gpx", ".gpx.gz", ".fit", ".fit.gz"].iter().any(|suffix| name.ends_with(suffix))
+}
+
+/// Expands the input paths into a sorted list of GPX and FIT files, walking directories recursively.
+fn collect_files(paths: &[PathBuf]) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending: Vec<PathBuf> = paths.to_vec();
+
+    while let Some(path) = pending.pop() {
+        if path.is_dir() {
+            for entry in fs::read_dir(&path)? {
+                let entry_path = entry?.path();
+                if entry_path.is_dir() || is_gpx_file(&entry_path) {
+                    pending.push(entry_path);
+                }
+            }
+        } else {
+            // Explicitly named files are processed whatever their extension
+            files.push(path);
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Builds the output path for an input file, e.g. `ride.gpx.gz` -> `ride.reduced.json`.
+fn output_path(input: &Path, out_dir: Option<&Path>, suffix: &str) -> PathBuf {
+    let name = input.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let lower = name.to_lowercase();
+    let stem_len = [".gpx.gz", ".gpx", ".fit.gz", ".fit"]
+        .iter()
+        .find(|suffix| lower.ends_with(*suffix))
+        .map_or(name.len(), |suffix| name.len() - suffix.len());
+
+    let file_name = format!("{}.{}", &name[..stem_len], suffix);
+    match out_dir {
+        Some(dir) => dir.join(file_name),
+        None => input.with_file_name(file_name),
+    }
+}
+
+/// Error from processing a single file: either I/O or a processing failure.
+enum FileError {
+    Io(std::io::Error),
+    Processing(GpxProcessorError),
+}
+
+impl From<std::io::Error> for FileError {
+    fn from(error: std::io::Error) -> Self {
+        FileError::Io(error)
+    }
+}
+
+impl From<GpxProcessorError> for FileError {
+    fn from(error: GpxProcessorError) -> Self {
+        FileError::Processing(error)
+    }
+}
+
+/// Runs the command on one file.
+fn process_file(args: &Args, path: &Path) -> Result<(), FileError> {
+    let data = fs::read(path)?;
+
+    match args.command {
+        Command::Analyze => {
+            let processed = process_upload(&data, &args.options, &args.analysis_options)?;
+            let line = serde_json::json!({ "file": path, "analysis": processed.analysis });
+            println!("{}", line);
+        }
+        Command::Reduce => {
+            let reduced = reduce_upload_with_options(&data, &args.options)?;
+            fs::write(output_path(path, args.out_dir.as_deref(), "reduced.json"), reduced)?;
+        }
+        Command::Compress => {
+            let processed = process_upload(&data, &args.options, &args.analysis_options)?;
+            // Payloads are reduced data, not GPX, so they are named for their encoding;
+            // only gzip output is a standard file, other codecs carry the crate's own header
+            let suffix = match (args.options.payload_format(), args.options.compression_options().codec()) {
+                (PayloadFormat::Json, CompressionCodec::Gzip) => "smlr.json.gz",
+                (PayloadFormat::Json, _) => "smlr.json.mbc",
+                (PayloadFormat::Binary, CompressionCodec::Gzip) => "smlr.bin.gz",
+                (PayloadFormat::Binary, _) => "smlr.bin.mbc",
+            };
+            fs::write(output_path(path, args.out_dir.as_deref(), suffix), processed.payload)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) if message.is_empty() => {
+            print!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    if let Some(dir) = &args.out_dir
+        && let Err(e) = fs::create_dir_all(dir)
+    {
+        eprintln!("error: cannot create {}: {}", dir.display(), e);
+        return ExitCode::FAILURE;
+    }
+
+    let files = match collect_files(&args.paths) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // Keep going after a bad file so one corrupt ride doesn't stop a batch
+    let mut failures = 0;
+    for path in &files {
+        match process_file(&args, path) {
+            Ok(()) => {}
+            Err(FileError::Io(e)) => {
+                failures += 1;
+                eprintln!("{}: {}", path.display(), e);
+            }
+            Err(FileError::Processing(e)) => {
+                failures += 1;
+                eprintln!("{}: {} [{}]", path.display(), e, e.code());
+            }
+        }
+    }
+
+    if failures > 0 {
+        eprintln!("{} of {} files failed", failures, files.len());
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,369 @@
+//! Stateful GPX Document Module
+//!
+//! This module exposes [`GpxDocument`], a WebAssembly class that parses a GPX
+//! file once and then serves repeated operations on it. The UI can re-run
+//! reduction with different settings, preview the result on the map and
+//! compress the chosen version without re-uploading or re-parsing the file.
+//! FIT activities are decoded into the same `Gpx` value and work the same way.
+
+use std::ops::Range;
+
+use gpx::{Gpx, Route, Track, TrackSegment};  // GPX parsing and representation
+use serde::Serialize;                  // Serialization framework
+use serde_json::{json, Value};         // GeoJSON construction
+use wasm_bindgen::prelude::*;          // WebAssembly bindings
+
+use crate::error::Result;
+use crate::{
+    calculate_bounding_box, count_points,
+    gpx_processing::{fit::{self, SensorSamples}, input::{self, Upload}, payload, pipeline, polyline::{self, PolylinePrecision}, profile, reduce::{self, ReduceOptions, ReduceReport}, stats::AnalysisOptions},
+    parse_gpx_from_string, SmlrGpx, SmlrTrackPoint,
+};
+
+/// A parsed GPX file that can be analyzed, reduced, cropped and compressed repeatedly.
+///
+/// The document keeps the original `Gpx` value alongside the most recent
+/// reduction, so `compress()` and `to_geojson()` always describe whatever
+/// `reduce()` last produced (the default [`ReduceOptions`] until then).
+#[wasm_bindgen]
+pub struct GpxDocument {
+    gpx: Gpx,                 // The parsed original file, cropped if `crop` was called
+    original_size: usize,     // Size in bytes of the uploaded GPX text or FIT file
+    samples: Option<SensorSamples>, // Heart rate, cadence and power of each track point of a FIT upload
+    options: ReduceOptions,   // Options used for the current reduction
+    analysis_options: AnalysisOptions, // Thresholds used by `analysis()`
+    reduced: SmlrGpx,         // Current reduced form of `gpx`
+    report: ReduceReport,     // Deviation report for the current reduction
+}
+
+#[wasm_bindgen]
+impl GpxDocument {
+    /// Parses a GPX string into a document.
+    ///
+    /// # Arguments
+    /// * `gpx_string` - The raw GPX file content as a string
+    ///
+    /// # Errors
+    /// * `TooLarge`, `TooManyPoints` or `ParseError` if the file cannot be processed
+    #[wasm_bindgen(constructor)]
+    pub fn new(gpx_string: &str) -> Result<GpxDocument> {
+        // Check input size before spending time on parsing
+        input::check_size(gpx_string.len())?; // 50MB max
+
+        let gpx = parse_gpx_from_string(gpx_string)?;
+        reduce::check_point_limit(&gpx)?;
+
+        Ok(GpxDocument::from_gpx(gpx, gpx_string.len(), None))
+    }
+
+    /// Parses raw file bytes into a document: GPX XML or a FIT activity,
+    /// plain or gzip-compressed (`.gpx.gz`, `.fit.gz`).
+    pub fn from_bytes(data: &[u8]) -> Result<GpxDocument> {
+        match input::decode_upload(data)? {
+            Upload::Gpx(gpx_string) => GpxDocument::new(&gpx_string),
+            Upload::Fit(bytes) => {
+                // Decoding checks the size and point-count limits itself
+                let activity = fit::decode_fit(&bytes)?;
+                Ok(GpxDocument::from_gpx(activity.gpx, bytes.len(), Some(activity.samples)))
+            }
+        }
+    }
+
+    /// Number of track points, route points and waypoints in the (possibly cropped) original file.
+    #[wasm_bindgen(getter)]
+    pub fn point_count(&self) -> usize {
+        count_points(&self.gpx)
+    }
+
+    /// Number of points in the current reduction.
+    #[wasm_bindgen(getter)]
+    pub fn reduced_point_count(&self) -> usize {
+        self.reduced.point_count()
+    }
+
+    /// Full analysis of the document using the current reduction options.
+    ///
+    /// # Returns
+    /// * `Result<JsValue>` - A JavaScript object containing analysis data or an error
+    pub fn analysis(&self) -> Result<JsValue> {
+        let processed = pipeline::process_parsed_gpx(&self.gpx, self.samples.as_ref(), self.original_size, &self.options, &self.analysis_options)?;
+        to_js(&processed.analysis)
+    }
+
+    /// Elevation-vs-distance profile of the (possibly cropped) original file for charting.
+    ///
+    /// # Arguments
+    /// * `samples` - Number of evenly spaced samples, capped at `MAX_PROFILE_SAMPLES`
+    ///
+    /// # Returns
+    /// * `Result<JsValue>` - The profile, or `undefined` if the file has no elevations
+    pub fn elevation_profile(&self, samples: usize) -> Result<JsValue> {
+        match profile::elevation_profile(&self.gpx, samples) {
+            Some(elevation_profile) => to_js(&elevation_profile),
+            None => Ok(JsValue::UNDEFINED),
+        }
+    }
+
+    /// Replaces the thresholds used for the ride statistics in `analysis()`.
+    pub fn set_analysis_options(&mut self, analysis_options: &AnalysisOptions) {
+        self.analysis_options = *analysis_options;
+    }
+
+    /// Re-reduces the document with new options and returns the reduced GPX.
+    ///
+    /// The new reduction replaces the current one for `compress()`,
+    /// `to_geojson()` and `analysis()`.
+    ///
+    /// # Arguments
+    /// * `options` - Precision and simplification settings for the reduction
+    ///
+    /// # Returns
+    /// * `Result<JsValue>` - The reduced GPX as a JavaScript object
+    pub fn reduce(&mut self, options: &ReduceOptions) -> Result<JsValue> {
+        self.options = *options;
+        self.rereduce();
+        to_js(&self.reduced)
+    }
+
+    /// Largest distance in metres between a dropped point and the current reduced line.
+    #[wasm_bindgen(getter)]
+    pub fn simplification_error_m(&self) -> f64 {
+        self.report.max_deviation_m
+    }
+
+    /// Serializes and compresses the current reduction, ready for upload.
+    ///
+    /// # Returns
+    /// * `Result<Vec<u8>>` - The compressed binary data or an error
+    pub fn compress(&self) -> Result<Vec<u8>> {
+        Ok(payload::build_payload(&self.reduced, &self.options)?.bytes)
+    }
+
+    /// Converts the current reduction into a GeoJSON `FeatureCollection`.
+    ///
+    /// Each track becomes one `MultiLineString` feature with one line per
+    /// segment, each route a `LineString` and each waypoint a `Point` with
+    /// its name and symbol; coordinates are `[lon, lat]` or `[lon, lat, ele]`.
+    /// Tracks with heart rate, cadence or power also get `coordinateProperties`
+    /// with `hr`, `cad` and `pwr` arrays shaped like their coordinates, holding
+    /// `null` where a point has no value.
+    ///
+    /// # Returns
+    /// * `Result<JsValue>` - The GeoJSON as a plain JavaScript object
+    pub fn to_geojson(&self) -> Result<JsValue> {
+        let position = |lat: f64, lon: f64, ele: Option<f64>| match ele {
+            Some(ele) => vec![lon, lat, ele],
+            None => vec![lon, lat],
+        };
+
+        let tracks = self.reduced.trk.iter()
+            .enumerate()
+            .map(|(track_index, track)| {
+                let lines: Vec<Vec<Vec<f64>>> = track.trkseg.iter()
+                    .map(|segment| segment.trkpt.iter().map(|point| position(point.lat, point.lon, point.ele)).collect())
+                    .collect();
+                let mut properties = json!({ "track_index": track_index });
+
+                let points = || track.trkseg.iter().flat_map(|segment| &segment.trkpt);
+                if points().any(|point| point.hr.is_some() || point.cad.is_some() || point.pwr.is_some()) {
+                    let values = |value: fn(&SmlrTrackPoint) -> Option<u16>| -> Vec<Vec<Option<u16>>> {
+                        track.trkseg.iter().map(|segment| segment.trkpt.iter().map(value).collect()).collect()
+                    };
+                    properties["coordinateProperties"] = json!({
+                        "hr": values(|point| point.hr),
+                        "cad": values(|point| point.cad),
+                        "pwr": values(|point| point.pwr),
+                    });
+                }
+
+                json!({
+                    "type": "Feature",
+                    "properties": properties,
+                    "geometry": { "type": "MultiLineString", "coordinates": lines },
+                })
+            });
+        let routes = self.reduced.rte.iter()
+            .enumerate()
+            .map(|(route_index, route)| {
+                let line: Vec<Vec<f64>> = route.rtept.iter().map(|point| position(point.lat, point.lon, point.ele)).collect();
+                json!({
+                    "type": "Feature",
+                    "properties": { "route_index": route_index },
+                    "geometry": { "type": "LineString", "coordinates": line },
+                })
+            });
+        let waypoints = self.reduced.wpt.iter()
+            .map(|waypoint| json!({
+                "type": "Feature",
+                "properties": { "name": waypoint.name, "sym": waypoint.sym },
+                "geometry": { "type": "Point", "coordinates": position(waypoint.lat, waypoint.lon, waypoint.ele) },
+            }));
+        let features: Vec<Value> = tracks.chain(routes).chain(waypoints).collect();
+
+        to_js(&json!({ "type": "FeatureCollection", "features": features }))
+    }
+
+    /// Encodes the current reduction as Google encoded polylines.
+    ///
+    /// # Arguments
+    /// * `precision` - Decimal places for latitude and longitude
+    /// * `with_elevation` - Whether to add elevation in centimetres as a third dimension
+    ///
+    /// # Returns
+    /// * `Result<JsValue>` - An object with `tracks`, one array of polyline strings (one per
+    ///   segment) per track, and `routes`, one polyline string per route
+    pub fn to_encoded_polylines(&self, precision: PolylinePrecision, with_elevation: bool) -> Result<JsValue> {
+        to_js(&polyline::encode_polylines(&self.reduced, precision, with_elevation))
+    }
+
+    /// Geographical bounds of the (possibly cropped) original file, or `undefined` if it has no points.
+    pub fn bounds(&self) -> Result<JsValue> {
+        match calculate_bounding_box(&self.gpx) {
+            Some(bounding_box) => to_js(&bounding_box),
+            None => Ok(JsValue::UNDEFINED),
+        }
+    }
+
+    /// Crops the document to a bounding box and re-runs the current reduction.
+    ///
+    /// Points outside the box are removed. Where a segment or route leaves
+    /// and re-enters the box it is split in two, so no line is drawn across
+    /// the cropped-out part; tracks and routes left without points are dropped.
+    ///
+    /// # Arguments
+    /// * `min_lat`, `min_lon`, `max_lat`, `max_lon` - The box to keep, in decimal degrees
+    ///
+    /// # Returns
+    /// * `usize` - The number of track points, route points and waypoints remaining
+    pub fn crop(&mut self, min_lat: f64, min_lon: f64, max_lat: f64, max_lon: f64) -> usize {
+        let inside = |point: &gpx::Waypoint| {
+            let (lat, lon) = (point.point().y(), point.point().x());
+            (min_lat..=max_lat).contains(&lat) && (min_lon..=max_lon).contains(&lon)
+        };
+
+        // Sensor samples are cropped with their points, so the summary describes what is left
+        let tracks = std::mem::take(&mut self.gpx.tracks);
+        let mut cropped_samples = SensorSamples::new();
+        for (track_index, track) in tracks.into_iter().enumerate() {
+            let mut segments = Vec::new();
+            let mut segment_samples = Vec::new();
+            for (segment_index, segment) in track.segments.iter().enumerate() {
+                let samples = self.samples.as_ref().and_then(|samples| samples.get(track_index)?.get(segment_index));
+
+                // Split each segment into runs of consecutive points inside the box
+                for run in inside_runs(&segment.points, inside) {
+                    segment_samples.push(samples.and_then(|samples| samples.get(run.clone())).map(<[_]>::to_vec).unwrap_or_default());
+                    let mut cropped = TrackSegment::new();
+                    cropped.points = segment.points[run].to_vec();
+                    segments.push(cropped);
+                }
+            }
+
+            if !segments.is_empty() {
+                self.gpx.tracks.push(Track { segments, ..track });
+                cropped_samples.push(segment_samples);
+            }
+        }
+        if self.samples.is_some() {
+            self.samples = Some(cropped_samples);
+        }
+
+        let routes = std::mem::take(&mut self.gpx.routes);
+        self.gpx.routes = routes.into_iter()
+            .flat_map(|mut route| {
+                // Keep the route's name and other details on each piece, without copying its points
+                let points = std::mem::take(&mut route.points);
+                points
+                    .split(|point| !inside(point))
+                    .filter(|run| !run.is_empty())
+                    .map(|run| Route { points: run.to_vec(), ..route.clone() })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        self.gpx.waypoints.retain(inside);
+
+        self.rereduce();
+        self.point_count()
+    }
+}
+
+impl GpxDocument {
+    /// Builds a document around a decoded file that has passed the size and point-count limits.
+    fn from_gpx(gpx: Gpx, original_size: usize, samples: Option<SensorSamples>) -> GpxDocument {
+        let options = ReduceOptions::default();
+        let (reduced, report) = reduce::reduce_gpx(&gpx, samples.as_ref(), &options);
+
+        GpxDocument {
+            gpx,
+            original_size,
+            samples,
+            options,
+            analysis_options: AnalysisOptions::default(),
+            reduced,
+            report,
+        }
+    }
+
+    /// Recomputes the reduction from the original data and current options.
+    fn rereduce(&mut self) {
+        let (reduced, report) = reduce::reduce_gpx(&self.gpx, self.samples.as_ref(), &self.options);
+        self.reduced = reduced;
+        self.report = report;
+    }
+}
+
+/// Index ranges of the runs of consecutive points for which `inside` holds.
+fn inside_runs(points: &[gpx::Waypoint], inside: impl Fn(&gpx::Waypoint) -> bool) -> Vec<Range<usize>> {
+    let mut runs = Vec::new();
+    let mut start = None;
+    for (index, point) in points.iter().enumerate() {
+        match (inside(point), start) {
+            (true, None) => start = Some(index),
+            (false, Some(run_start)) => {
+                runs.push(run_start..index);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(run_start) = start {
+        runs.push(run_start..points.len());
+    }
+    runs
+}
+
+/// Serializes a value to a plain JavaScript object (maps become objects, not `Map`s).
+fn to_js<T: Serialize>(value: &T) -> Result<JsValue> {
+    Ok(value.serialize(&serde_wasm_bindgen::Serializer::json_compatible())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use geo_types::Point;
+    use gpx::Waypoint;
+
+    use super::*;
+    use crate::gpx_processing::fit::{SensorSample, SensorStats, SensorSummary};
+
+    #[test]
+    fn crops_sensor_samples_with_their_points() {
+        // The middle point falls outside the box, splitting the segment in two
+        let mut segment = TrackSegment::new();
+        segment.points = [0.0, 1.0, 0.2].iter().map(|&lon| Waypoint::new(Point::new(lon, 45.0))).collect();
+        let mut track = Track::new();
+        track.segments = vec![segment];
+        let gpx = Gpx { tracks: vec![track], ..Gpx::default() };
+        let sample = |heart_rate: u16| SensorSample { heart_rate_bpm: Some(heart_rate), ..SensorSample::default() };
+
+        let mut document = GpxDocument::from_gpx(gpx, 0, Some(vec![vec![vec![sample(120), sample(180), sample(140)]]]));
+        assert_eq!(document.crop(44.0, -0.5, 46.0, 0.5), 2);
+
+        let samples = document.samples.as_ref().unwrap();
+        assert_eq!(samples, &vec![vec![vec![sample(120)], vec![sample(140)]]]);
+        let heart_rates: Vec<Option<u16>> = document.reduced.trk[0].trkseg.iter().map(|segment| segment.trkpt[0].hr).collect();
+        assert_eq!(heart_rates, vec![Some(120), Some(140)]);
+
+        let summary = SensorSummary::from_samples(samples.iter().flatten().flatten()).unwrap();
+        assert_eq!(summary.heart_rate_bpm, Some(SensorStats { average: 130.0, max: 140.0 }));
+    }
+}
@@ -0,0 +1,146 @@
+//! Error Types Module
+//!
+//! Every failure in the crate is reported as a [`GpxProcessorError`]. Each
+//! variant has a stable, machine-readable code so the frontend can react to
+//! specific failures instead of string-matching messages. At the WebAssembly
+//! boundary (with the `wasm` feature) the error becomes a JavaScript `Error`
+//! carrying `code` and `details` properties.
+
+use std::fmt;
+
+#[cfg(feature = "wasm")]
+use serde::Serialize;           // Serialization framework
+use serde_json::{json, Value};  // Structured error details
+#[cfg(feature = "wasm")]
+use wasm_bindgen::JsValue;      // WebAssembly <-> JavaScript interop
+use xml::common::Position;      // Line numbers for XML parse errors
+
+/// Convenience alias for results produced by this crate.
+pub type Result<T> = std::result::Result<T, GpxProcessorError>;
+
+/// Errors produced while decoding, processing or encoding GPX data.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GpxProcessorError {
+    /// The input, or its decompressed form, exceeds the size cap.
+    TooLarge { size: usize, max: usize },
+    /// The GPX or FIT file has more points than can be processed.
+    TooManyPoints { count: usize, max: usize },
+    /// The GPX or FIT file could not be parsed; `line` is 1-based when known (GPX XML only).
+    ParseError { line: Option<u64>, message: String },
+    /// The input does not look like a GPX document at all.
+    NotGpx,
+    /// The input bytes are not valid UTF-8 text.
+    InvalidEncoding(String),
+    /// Compressed input could not be decompressed.
+    Decompression(String),
+    /// The reduced payload could not be compressed.
+    Compression(String),
+    /// A stored payload is not in a format this crate can decode.
+    InvalidPayload(String),
+    /// A value could not be serialized for output.
+    Serialization(String),
+}
+
+impl GpxProcessorError {
+    /// Stable machine-readable code for this error, suitable for `switch` statements in JS.
+    pub fn code(&self) -> &'static str {
+        match self {
+            GpxProcessorError::TooLarge { .. } => "TOO_LARGE",
+            GpxProcessorError::TooManyPoints { .. } => "TOO_MANY_POINTS",
+            GpxProcessorError::ParseError { .. } => "PARSE_ERROR",
+            GpxProcessorError::NotGpx => "NOT_GPX",
+            GpxProcessorError::InvalidEncoding(_) => "INVALID_ENCODING",
+            GpxProcessorError::Decompression(_) => "DECOMPRESSION",
+            GpxProcessorError::Compression(_) => "COMPRESSION",
+            GpxProcessorError::InvalidPayload(_) => "INVALID_PAYLOAD",
+            GpxProcessorError::Serialization(_) => "SERIALIZATION",
+        }
+    }
+
+    /// Structured details for this error, exposed to JS as the `details` property.
+    pub fn details(&self) -> Value {
+        match self {
+            GpxProcessorError::TooLarge { size, max } => json!({ "size": size, "max": max }),
+            GpxProcessorError::TooManyPoints { count, max } => json!({ "count": count, "max": max }),
+            GpxProcessorError::ParseError { line, message } => json!({ "line": line, "message": message }),
+            GpxProcessorError::NotGpx => json!({}),
+            GpxProcessorError::InvalidEncoding(message)
+            | GpxProcessorError::Decompression(message)
+            | GpxProcessorError::Compression(message)
+            | GpxProcessorError::InvalidPayload(message)
+            | GpxProcessorError::Serialization(message) => json!({ "message": message }),
+        }
+    }
+}
+
+impl fmt::Display for GpxProcessorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GpxProcessorError::TooLarge { max, .. } => {
+                write!(f, "File too large (max {}MB)", max / 1_000_000)
+            }
+            GpxProcessorError::TooManyPoints { count, max } => {
+                write!(f, "File contains too many points ({} > {} max)", count, max)
+            }
+            GpxProcessorError::ParseError { line: Some(line), message } => {
+                write!(f, "Error parsing GPX on line {}: {}", line, message)
+            }
+            GpxProcessorError::ParseError { line: None, message } => {
+                write!(f, "Error parsing file: {}", message)
+            }
+            GpxProcessorError::NotGpx => write!(f, "Incorrect file format"),
+            GpxProcessorError::InvalidEncoding(message) => write!(f, "GPX file is not valid UTF-8: {}", message),
+            GpxProcessorError::Decompression(message) => write!(f, "Decompression error: {}", message),
+            GpxProcessorError::Compression(message) => write!(f, "Compression error: {}", message),
+            GpxProcessorError::InvalidPayload(message) => write!(f, "Invalid payload: {}", message),
+            GpxProcessorError::Serialization(message) => write!(f, "Serialization error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for GpxProcessorError {}
+
+impl From<gpx::errors::GpxError> for GpxProcessorError {
+    fn from(error: gpx::errors::GpxError) -> Self {
+        match error {
+            gpx::errors::GpxError::XmlParseError(xml_error) => GpxProcessorError::ParseError {
+                line: Some(xml_error.position().row + 1),
+                message: xml_error.to_string(),
+            },
+            other => GpxProcessorError::ParseError { line: None, message: other.to_string() },
+        }
+    }
+}
+
+impl From<serde_json::Error> for GpxProcessorError {
+    fn from(error: serde_json::Error) -> Self {
+        GpxProcessorError::Serialization(error.to_string())
+    }
+}
+
+#[cfg(feature = "wasm")]
+impl From<serde_wasm_bindgen::Error> for GpxProcessorError {
+    fn from(error: serde_wasm_bindgen::Error) -> Self {
+        GpxProcessorError::Serialization(error.to_string())
+    }
+}
+
+#[cfg(feature = "wasm")]
+impl From<GpxProcessorError> for JsValue {
+    /// Builds a JavaScript `Error` with `name`, `code` and `details` properties.
+    fn from(error: GpxProcessorError) -> Self {
+        let js_error = js_sys::Error::new(&error.to_string());
+        js_error.set_name("GpxProcessorError");
+
+        let details = error
+            .details()
+            .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+            .unwrap_or(JsValue::NULL);
+
+        // Setting properties on a fresh Error object cannot fail, so the results are ignored
+        let _ = js_sys::Reflect::set(&js_error, &JsValue::from_str("code"), &JsValue::from_str(error.code()));
+        let _ = js_sys::Reflect::set(&js_error, &JsValue::from_str("details"), &details);
+
+        js_error.into()
+    }
+}
@@ -0,0 +1,682 @@
+//! Binary Payload Module
+//!
+//! This module encodes the reduced `SmlrGpx` in a compact, versioned binary
+//! format instead of JSON text, and decodes it again. Before gzip the layout is:
+//!
+//! | Field                | Encoding                                         |
+//! |----------------------|--------------------------------------------------|
+//! | Magic                | `SMLR`                                           |
+//! | Version              | `u8`, currently 3                                |
+//! | Flags                | `u8`: which optional values and sections follow  |
+//! | Coordinate decimals  | `u8`, scale of the fixed-point lat/lon           |
+//! | Elevation decimals   | `u8`, scale of the fixed-point elevation         |
+//! | Body                 | Tracks → segments → points, counts as varints    |
+//! | Routes               | Routes → points, only if the routes flag is set  |
+//! | Waypoints            | Only if the waypoints flag is set                |
+//! | Metadata             | Only if the metadata flag is set                 |
+//!
+//! Coordinates are fixed-point integers at the reduction's precision, stored
+//! as zig-zag varint deltas from the previous point of the segment. Optional
+//! values (elevation, grade, time offset, heart rate, cadence, power) use `0` for missing and the zig-zag
+//! value plus one otherwise, and are only written when the flags say some
+//! point has them. Text (waypoint names and symbols, metadata) is stored the
+//! same way, as `0` or the UTF-8 length plus one followed by the bytes. Since the
+//! fixed-point scale matches the rounding applied during reduction, decoding
+//! reproduces the reduced values exactly.
+//!
+//! Version 2 added routes, waypoints and metadata behind their own flags.
+//! Track-only payloads are laid out exactly as in version 1, which can only set
+//! the elevation, grade and times flags and still decodes. Flag bits the
+//! payload's version does not define are rejected rather than skipped.
+//! Version 3 added the heart rate, cadence and power of FIT track points,
+//! written after each point's time offset when the sensors flag is set.
+
+use super::{compress, input::MAX_GPX_POINTS, metadata::RideMetadata, reduce::ReduceOptions};
+use crate::error::{GpxProcessorError, Result};
+use crate::{SmlrGpx, SmlrRoute, SmlrTrack, SmlrTrackPoint, SmlrTrackSegment, SmlrWaypoint};
+
+/// The four bytes that open every binary payload, before gzip.
+pub const MAGIC: [u8; 4] = *b"SMLR";
+
+/// Current version of the binary layout.
+pub const VERSION: u8 = 3;
+
+/// Flag bit set when at least one point has an elevation.
+const FLAG_ELEVATION: u8 = 0b000001;
+/// Flag bit set when at least one point has a grade.
+const FLAG_GRADE: u8 = 0b000010;
+/// Flag bit set when at least one segment or route has a start time.
+const FLAG_TIMES: u8 = 0b000100;
+/// Flag bit set when the payload has routes.
+const FLAG_ROUTES: u8 = 0b001000;
+/// Flag bit set when the payload has waypoints.
+const FLAG_WAYPOINTS: u8 = 0b010000;
+/// Flag bit set when the payload has a metadata block; part of the version 2 bump.
+const FLAG_METADATA: u8 = 0b100000;
+/// Flag bit set when at least one point has a heart rate, cadence or power; added in version 3.
+const FLAG_SENSORS: u8 = 0b1000000;
+
+/// Flag bits defined by version 1 of the layout.
+const FLAGS_V1: u8 = FLAG_ELEVATION | FLAG_GRADE | FLAG_TIMES;
+/// Flag bits defined by version 2 of the layout.
+const FLAGS_V2: u8 = FLAGS_V1 | FLAG_ROUTES | FLAG_WAYPOINTS | FLAG_METADATA;
+/// Flag bits defined by version 3 of the layout.
+const FLAGS_V3: u8 = FLAGS_V2 | FLAG_SENSORS;
+
+/// Decimal places kept on grades, matching the reduction.
+const GRADE_DECIMALS: u32 = 1;
+
+/// Largest fixed-point scale accepted in a header.
+pub(crate) const MAX_DECIMALS: u8 = 10;
+
+/// Largest magnitude of a fixed-point value, so deltas between two values cannot overflow.
+const MAX_FIXED: f64 = (1u64 << 53) as f64;
+
+/// Maps a signed integer onto an unsigned one so small magnitudes stay small.
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// Reverses [`zigzag`].
+fn unzigzag(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+/// Converts a value to a fixed-point integer with the given number of decimals.
+///
+/// # Errors
+/// * `Serialization` if the value is not finite or too large for the layout,
+///   e.g. an elevation of `1e300` from a malformed file
+fn to_fixed(value: f64, decimals: u32) -> Result<i64> {
+    let fixed = (value * 10f64.powi(decimals as i32)).round();
+    if !fixed.is_finite() || fixed.abs() > MAX_FIXED {
+        return Err(GpxProcessorError::Serialization(format!("value {} is out of range for the binary payload", value)));
+    }
+    Ok(fixed as i64)
+}
+
+/// Converts a fixed-point integer back to a value.
+fn from_fixed(value: i64, decimals: u32) -> f64 {
+    value as f64 / 10f64.powi(decimals as i32)
+}
+
+/// Appends values to a payload body.
+struct Writer {
+    bytes: Vec<u8>,
+    flags: u8,                 // Header flags, deciding which optional values are written
+    coordinate_decimals: u32,  // Fixed-point scale of lat/lon
+    elevation_decimals: u32,   // Fixed-point scale of elevation
+}
+
+impl Writer {
+    /// Appends an unsigned LEB128 varint.
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.bytes.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        self.bytes.push(value as u8);
+    }
+
+    /// Appends a signed value as a zig-zag varint.
+    fn signed(&mut self, value: i64) {
+        self.varint(zigzag(value));
+    }
+
+    /// Appends an optional signed value: `0` when missing, zig-zag plus one otherwise.
+    fn optional(&mut self, value: Option<i64>) {
+        self.varint(value.map_or(0, |value| zigzag(value).wrapping_add(1)));
+    }
+
+    /// Appends optional text: `0` when missing, the UTF-8 length plus one and the bytes otherwise.
+    fn text(&mut self, value: Option<&str>) {
+        match value {
+            Some(text) => {
+                self.varint(text.len() as u64 + 1);
+                self.bytes.extend_from_slice(text.as_bytes());
+            }
+            None => self.varint(0),
+        }
+    }
+
+    /// Appends a track segment or route: its start time, point count and points.
+    fn points(&mut self, t0: Option<i64>, points: &[SmlrTrackPoint]) -> Result<()> {
+        let flags = self.flags;
+        if flags & FLAG_TIMES != 0 {
+            self.optional(t0);
+        }
+        self.varint(points.len() as u64);
+
+        // Deltas restart at each segment so segments decode independently
+        let (mut lat, mut lon, mut ele, mut dt) = (0, 0, 0, 0);
+        for point in points {
+            let (point_lat, point_lon) = (to_fixed(point.lat, self.coordinate_decimals)?, to_fixed(point.lon, self.coordinate_decimals)?);
+            self.signed(point_lat - lat);
+            self.signed(point_lon - lon);
+            (lat, lon) = (point_lat, point_lon);
+
+            if flags & FLAG_ELEVATION != 0 {
+                let point_ele = point.ele.map(|value| to_fixed(value, self.elevation_decimals)).transpose()?;
+                self.optional(point_ele.map(|value| value - ele));
+                ele = point_ele.unwrap_or(ele);
+            }
+            if flags & FLAG_GRADE != 0 {
+                self.optional(point.grade.map(|value| to_fixed(value, GRADE_DECIMALS)).transpose()?);
+            }
+            if flags & FLAG_TIMES != 0 {
+                self.optional(point.dt.map(|value| value.wrapping_sub(dt)));
+                dt = point.dt.unwrap_or(dt);
+            }
+            if flags & FLAG_SENSORS != 0 {
+                for value in [point.hr, point.cad, point.pwr] {
+                    self.optional(value.map(i64::from));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads values from a payload body, failing cleanly on truncated input.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    flags: u8,                 // Header flags, deciding which optional values are present
+    coordinate_decimals: u32,  // Fixed-point scale of lat/lon
+    elevation_decimals: u32,   // Fixed-point scale of elevation
+    total_points: usize,       // Points read so far, checked against the point limit
+}
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8> {
+        let byte = *self.bytes.get(self.position).ok_or_else(|| invalid("unexpected end of payload"))?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn varint(&mut self) -> Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(invalid("varint longer than 64 bits"))
+    }
+
+    fn signed(&mut self) -> Result<i64> {
+        Ok(unzigzag(self.varint()?))
+    }
+
+    fn optional(&mut self) -> Result<Option<i64>> {
+        Ok(match self.varint()? {
+            0 => None,
+            value => Some(unzigzag(value - 1)),
+        })
+    }
+
+    /// Reads a count, rejecting values that could not fit in the remaining bytes.
+    ///
+    /// Every item takes at least one byte, so this bounds allocations by the payload size.
+    fn count(&mut self) -> Result<usize> {
+        let count = self.varint()?;
+        if count > (self.bytes.len() - self.position) as u64 {
+            return Err(invalid("count exceeds payload size"));
+        }
+        Ok(count as usize)
+    }
+
+    /// Reads optional text written by [`Writer::text`].
+    fn text(&mut self) -> Result<Option<String>> {
+        let length = match self.varint()? {
+            0 => return Ok(None),
+            length => length - 1,
+        };
+        let end = usize::try_from(length).ok()
+            .and_then(|length| self.position.checked_add(length))
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or_else(|| invalid("unexpected end of payload"))?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        String::from_utf8(bytes.to_vec()).map(Some).map_err(|_| invalid("text is not valid UTF-8"))
+    }
+
+    /// Counts points towards the limit, failing once the payload holds too many.
+    fn add_points(&mut self, count: usize) -> Result<()> {
+        self.total_points += count;
+        if self.total_points > MAX_GPX_POINTS {
+            return Err(GpxProcessorError::TooManyPoints { count: self.total_points, max: MAX_GPX_POINTS });
+        }
+        Ok(())
+    }
+
+    /// Reads an optional heart rate, cadence or power.
+    fn sensor(&mut self) -> Result<Option<u16>> {
+        self.optional()?
+            .map(|value| u16::try_from(value).map_err(|_| invalid("sensor value out of range")))
+            .transpose()
+    }
+
+    /// Reads a track segment or route written by [`Writer::points`].
+    fn points(&mut self) -> Result<(Option<i64>, Vec<SmlrTrackPoint>)> {
+        let flags = self.flags;
+        let t0 = if flags & FLAG_TIMES != 0 { self.optional()? } else { None };
+        let point_count = self.count()?;
+        self.add_points(point_count)?;
+
+        let (mut lat, mut lon, mut ele, mut dt) = (0i64, 0i64, 0i64, 0i64);
+        let mut points = Vec::with_capacity(point_count);
+        for _ in 0..point_count {
+            lat = lat.wrapping_add(self.signed()?);
+            lon = lon.wrapping_add(self.signed()?);
+
+            let point_ele = if flags & FLAG_ELEVATION != 0 {
+                self.optional()?.map(|delta| {
+                    ele = ele.wrapping_add(delta);
+                    from_fixed(ele, self.elevation_decimals)
+                })
+            } else {
+                None
+            };
+            let grade = if flags & FLAG_GRADE != 0 {
+                self.optional()?.map(|value| from_fixed(value, GRADE_DECIMALS))
+            } else {
+                None
+            };
+            let point_dt = if flags & FLAG_TIMES != 0 {
+                self.optional()?.map(|delta| {
+                    dt = dt.wrapping_add(delta);
+                    dt
+                })
+            } else {
+                None
+            };
+            let (hr, cad, pwr) = if flags & FLAG_SENSORS != 0 {
+                (self.sensor()?, self.sensor()?, self.sensor()?)
+            } else {
+                (None, None, None)
+            };
+
+            points.push(SmlrTrackPoint {
+                lat: from_fixed(lat, self.coordinate_decimals),
+                lon: from_fixed(lon, self.coordinate_decimals),
+                ele: point_ele,
+                grade,
+                dt: point_dt,
+                hr,
+                cad,
+                pwr,
+            });
+        }
+
+        Ok((t0, points))
+    }
+}
+
+/// Builds an `InvalidPayload` error.
+fn invalid(message: &str) -> GpxProcessorError {
+    GpxProcessorError::InvalidPayload(message.to_string())
+}
+
+/// Serializes a reduced GPX to the uncompressed binary layout.
+///
+/// # Arguments
+/// * `gpx` - The reduced GPX
+/// * `options` - The options it was reduced with, which set the fixed-point scales
+///
+/// # Returns
+/// * `Result<Vec<u8>>` - The binary payload before gzip
+///
+/// # Errors
+/// * `Serialization` if a coordinate, elevation or grade is not finite or too large to encode
+pub fn to_bytes(gpx: &SmlrGpx, options: &ReduceOptions) -> Result<Vec<u8>> {
+    let coordinate_decimals = options.coordinate_decimals();
+    let elevation_decimals = options.elevation_decimals().min(u32::from(MAX_DECIMALS));
+
+    let points = || gpx.trk.iter()
+        .flat_map(|track| &track.trkseg)
+        .flat_map(|segment| &segment.trkpt)
+        .chain(gpx.rte.iter().flat_map(|route| &route.rtept));
+    let mut flags = 0;
+    if points().any(|point| point.ele.is_some()) || gpx.wpt.iter().any(|waypoint| waypoint.ele.is_some()) {
+        flags |= FLAG_ELEVATION;
+    }
+    if points().any(|point| point.grade.is_some()) {
+        flags |= FLAG_GRADE;
+    }
+    if points().any(|point| point.hr.is_some() || point.cad.is_some() || point.pwr.is_some()) {
+        flags |= FLAG_SENSORS;
+    }
+    if gpx.trk.iter().flat_map(|track| &track.trkseg).any(|segment| segment.t0.is_some())
+        || gpx.rte.iter().any(|route| route.t0.is_some())
+    {
+        flags |= FLAG_TIMES;
+    }
+    if !gpx.rte.is_empty() {
+        flags |= FLAG_ROUTES;
+    }
+    if !gpx.wpt.is_empty() {
+        flags |= FLAG_WAYPOINTS;
+    }
+    if gpx.metadata.is_some() {
+        flags |= FLAG_METADATA;
+    }
+
+    let mut writer = Writer { bytes: Vec::new(), flags, coordinate_decimals, elevation_decimals };
+    writer.bytes.extend_from_slice(&MAGIC);
+    writer.bytes.extend_from_slice(&[VERSION, flags, coordinate_decimals as u8, elevation_decimals as u8]);
+
+    writer.varint(gpx.trk.len() as u64);
+    for track in &gpx.trk {
+        writer.varint(track.trkseg.len() as u64);
+        for segment in &track.trkseg {
+            writer.points(segment.t0, &segment.trkpt)?;
+        }
+    }
+
+    if flags & FLAG_ROUTES != 0 {
+        writer.varint(gpx.rte.len() as u64);
+        for route in &gpx.rte {
+            writer.points(route.t0, &route.rtept)?;
+        }
+    }
+
+    if flags & FLAG_WAYPOINTS != 0 {
+        writer.varint(gpx.wpt.len() as u64);
+        let (mut lat, mut lon, mut ele) = (0, 0, 0);
+        for waypoint in &gpx.wpt {
+            let (waypoint_lat, waypoint_lon) = (to_fixed(waypoint.lat, coordinate_decimals)?, to_fixed(waypoint.lon, coordinate_decimals)?);
+            writer.signed(waypoint_lat - lat);
+            writer.signed(waypoint_lon - lon);
+            (lat, lon) = (waypoint_lat, waypoint_lon);
+
+            if flags & FLAG_ELEVATION != 0 {
+                let waypoint_ele = waypoint.ele.map(|value| to_fixed(value, elevation_decimals)).transpose()?;
+                writer.optional(waypoint_ele.map(|value| value - ele));
+                ele = waypoint_ele.unwrap_or(ele);
+            }
+            writer.text(waypoint.name.as_deref());
+            writer.text(waypoint.sym.as_deref());
+        }
+    }
+
+    if let Some(metadata) = &gpx.metadata {
+        writer.text(metadata.name.as_deref());
+        writer.text(metadata.desc.as_deref());
+        writer.text(metadata.activity_type.as_deref());
+        writer.optional(metadata.time);
+        writer.text(metadata.creator.as_deref());
+    }
+
+    Ok(writer.bytes)
+}
+
+/// Parses the uncompressed binary layout back into a reduced GPX.
+///
+/// # Arguments
+/// * `bytes` - The binary payload after gunzip
+///
+/// # Returns
+/// * `Result<SmlrGpx>` - The reduced GPX
+///
+/// # Errors
+/// * `InvalidPayload` if the magic, version or body is malformed
+/// * `TooManyPoints` if the payload holds more than [`MAX_GPX_POINTS`] points
+pub fn from_bytes(bytes: &[u8]) -> Result<SmlrGpx> {
+    if !bytes.starts_with(&MAGIC) {
+        return Err(invalid("missing SMLR header"));
+    }
+    let mut reader = Reader {
+        bytes,
+        position: MAGIC.len(),
+        flags: 0,
+        coordinate_decimals: 0,
+        elevation_decimals: 0,
+        total_points: 0,
+    };
+
+    let version = reader.byte()?;
+    let known_flags = match version {
+        1 => FLAGS_V1,
+        2 => FLAGS_V2,
+        3 => FLAGS_V3,
+        _ => return Err(GpxProcessorError::InvalidPayload(format!("unsupported version {}", version))),
+    };
+    let flags = reader.byte()?;
+    if flags & !known_flags != 0 {
+        return Err(GpxProcessorError::InvalidPayload(format!("unknown flags {:#010b} for version {}", flags & !known_flags, version)));
+    }
+    let (coordinate_decimals, elevation_decimals) = (reader.byte()?, reader.byte()?);
+    if coordinate_decimals > MAX_DECIMALS || elevation_decimals > MAX_DECIMALS {
+        return Err(invalid("fixed-point scale out of range"));
+    }
+    let (coordinate_decimals, elevation_decimals) = (u32::from(coordinate_decimals), u32::from(elevation_decimals));
+    (reader.flags, reader.coordinate_decimals, reader.elevation_decimals) = (flags, coordinate_decimals, elevation_decimals);
+
+    let track_count = reader.count()?;
+    let mut trk = Vec::with_capacity(track_count);
+    for _ in 0..track_count {
+        let segment_count = reader.count()?;
+        let mut trkseg = Vec::with_capacity(segment_count);
+        for _ in 0..segment_count {
+            let (t0, trkpt) = reader.points()?;
+            trkseg.push(SmlrTrackSegment { t0, trkpt });
+        }
+        trk.push(SmlrTrack { trkseg });
+    }
+
+    let mut rte = Vec::new();
+    if flags & FLAG_ROUTES != 0 {
+        let route_count = reader.count()?;
+        rte.reserve(route_count);
+        for _ in 0..route_count {
+            let (t0, rtept) = reader.points()?;
+            rte.push(SmlrRoute { t0, rtept });
+        }
+    }
+
+    let mut wpt = Vec::new();
+    if flags & FLAG_WAYPOINTS != 0 {
+        let waypoint_count = reader.count()?;
+        reader.add_points(waypoint_count)?;
+        wpt.reserve(waypoint_count);
+        let (mut lat, mut lon, mut ele) = (0i64, 0i64, 0i64);
+        for _ in 0..waypoint_count {
+            lat = lat.wrapping_add(reader.signed()?);
+            lon = lon.wrapping_add(reader.signed()?);
+            let waypoint_ele = if flags & FLAG_ELEVATION != 0 {
+                reader.optional()?.map(|delta| {
+                    ele = ele.wrapping_add(delta);
+                    from_fixed(ele, elevation_decimals)
+                })
+            } else {
+                None
+            };
+
+            wpt.push(SmlrWaypoint {
+                lat: from_fixed(lat, coordinate_decimals),
+                lon: from_fixed(lon, coordinate_decimals),
+                ele: waypoint_ele,
+                name: reader.text()?,
+                sym: reader.text()?,
+            });
+        }
+    }
+
+    let metadata = if flags & FLAG_METADATA != 0 {
+        Some(RideMetadata {
+            name: reader.text()?,
+            desc: reader.text()?,
+            activity_type: reader.text()?,
+            time: reader.optional()?,
+            creator: reader.text()?,
+        })
+    } else {
+        None
+    };
+
+    if reader.position != bytes.len() {
+        return Err(invalid("trailing bytes after payload"));
+    }
+
+    Ok(SmlrGpx { metadata, trk, wpt, rte })
+}
+
+/// Encodes a reduced GPX as a compressed binary payload.
+///
+/// # Arguments
+/// * `gpx` - The reduced GPX
+/// * `options` - The options it was reduced with, which set the fixed-point scales and compression
+///
+/// # Returns
+/// * `Result<Vec<u8>>` - The compressed payload, or a `Serialization` or `Compression` error
+pub fn encode_smlr_gpx(gpx: &SmlrGpx, options: &ReduceOptions) -> Result<Vec<u8>> {
+    compress::compress_bytes(&to_bytes(gpx, options)?, &options.compression_options())
+}
+
+/// Decodes a compressed binary payload back into a reduced GPX, detecting the codec.
+///
+/// Decompression stops one byte past the 50MB input cap, so a malicious
+/// payload cannot inflate without bound.
+///
+/// # Arguments
+/// * `data` - The compressed payload
+///
+/// # Returns
+/// * `Result<SmlrGpx>` - The reduced GPX
+///
+/// # Errors
+/// * `Decompression` if the codec is unrecognised or the stream is corrupt
+/// * `TooLarge` if it inflates past 50MB
+/// * `InvalidPayload` or `TooManyPoints` if the decompressed payload is rejected
+pub fn decode_smlr_gpx(data: &[u8]) -> Result<SmlrGpx> {
+    from_bytes(&compress::decompress_bytes(data)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(lat: f64, lon: f64, ele: Option<f64>, grade: Option<f64>, dt: Option<i64>) -> SmlrTrackPoint {
+        SmlrTrackPoint { lat, lon, ele, grade, dt, hr: None, cad: None, pwr: None }
+    }
+
+    fn sample() -> SmlrGpx {
+        SmlrGpx {
+            metadata: Some(RideMetadata {
+                name: Some("Morning ride".to_string()),
+                desc: None,
+                activity_type: Some("cycling".to_string()),
+                time: Some(1_700_000_000),
+                creator: Some("Garmin".to_string()),
+            }),
+            trk: vec![SmlrTrack {
+                trkseg: vec![
+                    SmlrTrackSegment {
+                        t0: Some(1_700_000_000),
+                        trkpt: vec![
+                            point(45.12345, -122.54321, Some(102.5), Some(3.2), Some(0)),
+                            point(45.12401, -122.54298, None, None, Some(4)),
+                            point(45.12466, -122.54207, Some(98.1), Some(-1.5), None),
+                        ],
+                    },
+                    SmlrTrackSegment { t0: None, trkpt: vec![point(-33.5, 151.25, Some(-3.0), None, None)] },
+                ],
+            }],
+            wpt: vec![SmlrWaypoint {
+                lat: 45.125,
+                lon: -122.5,
+                ele: Some(110.0),
+                name: Some("Summit".to_string()),
+                sym: None,
+            }],
+            rte: vec![SmlrRoute { t0: None, rtept: vec![point(46.0, -121.0, None, None, None), point(46.001, -121.002, None, None, None)] }],
+        }
+    }
+
+    #[test]
+    fn round_trips_every_section() {
+        let gpx = sample();
+        let bytes = to_bytes(&gpx, &ReduceOptions::default()).unwrap();
+        assert_eq!(from_bytes(&bytes).unwrap(), gpx);
+    }
+
+    #[test]
+    fn round_trips_through_compression() {
+        let gpx = sample();
+        let encoded = encode_smlr_gpx(&gpx, &ReduceOptions::default()).unwrap();
+        assert_eq!(decode_smlr_gpx(&encoded).unwrap(), gpx);
+    }
+
+    #[test]
+    fn rejects_out_of_range_values() {
+        let mut gpx = sample();
+        gpx.trk[0].trkseg[0].trkpt[1].ele = Some(1e300);
+        assert!(matches!(to_bytes(&gpx, &ReduceOptions::default()), Err(GpxProcessorError::Serialization(_))));
+
+        gpx.trk[0].trkseg[0].trkpt[1].ele = Some(f64::NAN);
+        assert!(matches!(to_bytes(&gpx, &ReduceOptions::default()), Err(GpxProcessorError::Serialization(_))));
+    }
+
+    #[test]
+    fn decodes_version_1_payloads() {
+        let mut gpx = sample();
+        (gpx.metadata, gpx.wpt, gpx.rte) = (None, Vec::new(), Vec::new());
+
+        // Track-only payloads kept the version 1 layout
+        let mut bytes = to_bytes(&gpx, &ReduceOptions::default()).unwrap();
+        bytes[MAGIC.len()] = 1;
+        assert_eq!(from_bytes(&bytes).unwrap(), gpx);
+    }
+
+    #[test]
+    fn rejects_unknown_flags() {
+        let mut bytes = to_bytes(&sample(), &ReduceOptions::default()).unwrap();
+        bytes[MAGIC.len() + 1] |= 0b1000_0000;
+        assert!(matches!(from_bytes(&bytes), Err(GpxProcessorError::InvalidPayload(message)) if message.contains("unknown flags")));
+
+        // Routes and waypoints did not exist in version 1
+        let mut bytes = to_bytes(&sample(), &ReduceOptions::default()).unwrap();
+        bytes[MAGIC.len()] = 1;
+        assert!(matches!(from_bytes(&bytes), Err(GpxProcessorError::InvalidPayload(message)) if message.contains("unknown flags")));
+    }
+
+    #[test]
+    fn keeps_metadata_from_version_2() {
+        let mut gpx = sample();
+        (gpx.trk, gpx.wpt, gpx.rte) = (Vec::new(), Vec::new(), Vec::new());
+
+        let mut bytes = to_bytes(&gpx, &ReduceOptions::default()).unwrap();
+        assert_eq!((bytes[MAGIC.len()], bytes[MAGIC.len() + 1]), (VERSION, FLAG_METADATA));
+        assert_eq!(from_bytes(&bytes).unwrap(), gpx);
+
+        bytes[MAGIC.len()] = 1;
+        assert!(matches!(from_bytes(&bytes), Err(GpxProcessorError::InvalidPayload(message)) if message.contains("unknown flags")));
+    }
+
+    #[test]
+    fn keeps_sensors_from_version_3() {
+        let mut gpx = sample();
+        (gpx.metadata, gpx.wpt, gpx.rte) = (None, Vec::new(), Vec::new());
+        let points = &mut gpx.trk[0].trkseg[0].trkpt;
+        (points[0].hr, points[0].cad, points[0].pwr) = (Some(142), Some(88), Some(231));
+        points[2].pwr = Some(0);
+
+        let mut bytes = to_bytes(&gpx, &ReduceOptions::default()).unwrap();
+        assert_eq!(from_bytes(&bytes).unwrap(), gpx);
+
+        bytes[MAGIC.len()] = 2;
+        assert!(matches!(from_bytes(&bytes), Err(GpxProcessorError::InvalidPayload(message)) if message.contains("unknown flags")));
+    }
+
+    #[test]
+    fn rejects_truncated_payloads() {
+        let bytes = to_bytes(&sample(), &ReduceOptions::default()).unwrap();
+        for end in 0..bytes.len() {
+            assert!(from_bytes(&bytes[..end]).is_err());
+        }
+    }
+}
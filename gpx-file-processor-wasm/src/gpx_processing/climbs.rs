@@ -0,0 +1,271 @@
+//! Climb Detection Module
+//!
+//! This module finds sustained climbs in the elevation data of a parsed GPX
+//! file and categorises them:
+//! - A climb runs from a low point to the following high point, tolerating
+//!   dips of less than [`DIP_TOLERANCE_M`] along the way
+//! - Climbs with too little gain or too shallow an average grade are dropped
+//! - The rest are scored and placed in the HC/1–4 scheme
+//!
+//! Climbs never span segments, and points without an elevation are skipped.
+
+use gpx::Gpx;                          // GPX parsing and representation
+use serde::{Deserialize, Serialize};  // Serialization framework
+
+use super::{geo, stats::AnalysisOptions};
+
+/// Largest descent, in metres, allowed inside a climb before it is considered over.
+const DIP_TOLERANCE_M: f64 = 10.0;
+
+/// Horizontal distance, in metres, over which maximum grade is measured.
+///
+/// Shorter windows let a single noisy elevation sample produce absurd grades.
+const MAX_GRADE_WINDOW_M: f64 = 100.0;
+
+/// Category of a climb, from hardest (`HC`) to easiest (`4`).
+///
+/// Categories follow the common distance × grade scoring used by ride
+/// tracking services: the score is the climb's length in metres multiplied by
+/// its average grade in percent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ClimbCategory {
+    #[serde(rename = "HC")]
+    Hc,
+    #[serde(rename = "1")]
+    Cat1,
+    #[serde(rename = "2")]
+    Cat2,
+    #[serde(rename = "3")]
+    Cat3,
+    #[serde(rename = "4")]
+    Cat4,
+}
+
+impl ClimbCategory {
+    /// Category for a climb's distance × average grade score, or `None` below category 4.
+    fn from_score(score: f64) -> Option<ClimbCategory> {
+        match score {
+            s if s >= 80_000.0 => Some(ClimbCategory::Hc),
+            s if s >= 64_000.0 => Some(ClimbCategory::Cat1),
+            s if s >= 32_000.0 => Some(ClimbCategory::Cat2),
+            s if s >= 16_000.0 => Some(ClimbCategory::Cat3),
+            s if s >= 8_000.0 => Some(ClimbCategory::Cat4),
+            _ => None,
+        }
+    }
+}
+
+/// A sustained climb found in a track.
+///
+/// Indices count the track's points across all of its segments, in file
+/// order, so they address the original (unreduced) points.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Climb {
+    pub track_index: usize,                 // Track the climb belongs to
+    pub start_index: usize,                 // Point at the foot of the climb
+    pub end_index: usize,                   // Point at the top of the climb
+    pub distance_m: f64,                    // Horizontal length of the climb
+    pub gain_m: f64,                        // Elevation difference from foot to top
+    pub average_grade_pct: f64,             // Gain over distance, in percent
+    pub max_grade_pct: f64,                 // Steepest grade over a 100 m window
+    pub category: Option<ClimbCategory>,    // HC/1–4, or `None` if uncategorised
+}
+
+/// A point of a segment that has an elevation.
+struct ProfilePoint {
+    index: usize,       // Index of the point within its track
+    distance_m: f64,    // Distance from the start of the segment
+    elevation: f64,     // Elevation in metres
+}
+
+/// Finds and categorises the climbs in a parsed GPX file.
+///
+/// # Arguments
+/// * `gpx` - The parsed GPX structure
+/// * `options` - Minimum gain and grade for a climb to be reported
+///
+/// # Returns
+/// * `Vec<Climb>` - The climbs, in file order
+pub fn detect_climbs(gpx: &Gpx, options: &AnalysisOptions) -> Vec<Climb> {
+    let mut climbs = Vec::new();
+
+    for (track_index, track) in gpx.tracks.iter().enumerate() {
+        let mut track_offset = 0;
+        for segment in &track.segments {
+            let coordinates: Vec<(f64, f64)> = segment.points.iter()
+                .map(|point| (point.point().y(), point.point().x()))
+                .collect();
+            let distances = geo::cumulative_distances_m(&coordinates);
+
+            let profile: Vec<ProfilePoint> = segment.points.iter()
+                .enumerate()
+                .filter_map(|(i, point)| point.elevation.map(|elevation| ProfilePoint {
+                    index: track_offset + i,
+                    distance_m: distances[i],
+                    elevation,
+                }))
+                .collect();
+
+            climbs.extend(
+                segment_climbs(&profile)
+                    .into_iter()
+                    .filter_map(|(start, end)| measure_climb(track_index, &profile[start..=end], options)),
+            );
+            track_offset += segment.points.len();
+        }
+    }
+
+    climbs
+}
+
+/// Splits a segment's profile into candidate climbs as `(start, end)` positions in `profile`.
+///
+/// The foot of a candidate is the lowest point since the previous one ended,
+/// even when a small dip inside the climb drops below where it started; its
+/// top is the highest point reached before the descent from it exceeds
+/// [`DIP_TOLERANCE_M`].
+fn segment_climbs(profile: &[ProfilePoint]) -> Vec<(usize, usize)> {
+    let mut candidates = Vec::new();
+    let (mut start, mut peak) = (0, 0);
+
+    for i in 1..profile.len() {
+        let elevation = profile[i].elevation;
+        if elevation >= profile[peak].elevation {
+            peak = i;
+        } else if profile[peak].elevation - elevation > DIP_TOLERANCE_M {
+            if peak > start {
+                candidates.push((start, peak));
+            }
+            start = i;
+            peak = i;
+        } else if elevation < profile[start].elevation {
+            // Still descending toward the foot, or a dip below it: the climb starts lower
+            start = i;
+            peak = i;
+        }
+    }
+    if peak > start {
+        candidates.push((start, peak));
+    }
+
+    candidates
+}
+
+/// Measures a candidate climb, returning `None` if it falls short of the reporting thresholds.
+fn measure_climb(track_index: usize, climb: &[ProfilePoint], options: &AnalysisOptions) -> Option<Climb> {
+    let (foot, top) = (climb.first()?, climb.last()?);
+    let distance_m = top.distance_m - foot.distance_m;
+    let gain_m = top.elevation - foot.elevation;
+    if distance_m <= 0.0 || gain_m < options.min_climb_gain_m() {
+        return None;
+    }
+
+    let average_grade_pct = gain_m / distance_m * 100.0;
+    if average_grade_pct < options.min_climb_grade_pct() {
+        return None;
+    }
+
+    // Steepest grade over any window of at least MAX_GRADE_WINDOW_M, or the whole climb if it is shorter
+    let mut max_grade_pct = average_grade_pct;
+    let mut window_start = 0;
+    for end in 1..climb.len() {
+        while window_start + 1 < end && climb[end].distance_m - climb[window_start + 1].distance_m >= MAX_GRADE_WINDOW_M {
+            window_start += 1;
+        }
+        let run = climb[end].distance_m - climb[window_start].distance_m;
+        if run >= MAX_GRADE_WINDOW_M {
+            let grade = (climb[end].elevation - climb[window_start].elevation) / run * 100.0;
+            max_grade_pct = max_grade_pct.max(grade);
+        }
+    }
+
+    Some(Climb {
+        track_index,
+        start_index: foot.index,
+        end_index: top.index,
+        distance_m,
+        gain_m,
+        average_grade_pct,
+        max_grade_pct,
+        category: ClimbCategory::from_score(distance_m * average_grade_pct),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use geo_types::Point;
+    use gpx::{Track, TrackSegment, Waypoint};
+
+    use super::*;
+
+    /// Degrees of latitude in 100 m along a meridian.
+    const STEP_DEG: f64 = 100.0 / 111_195.08;
+
+    /// Climbs of a single-segment track with points 100 m apart at the given elevations.
+    fn climbs(elevations: &[f64]) -> Vec<Climb> {
+        let mut segment = TrackSegment::new();
+        segment.points = elevations.iter()
+            .enumerate()
+            .map(|(i, &elevation)| {
+                let mut point = Waypoint::new(Point::new(0.0, i as f64 * STEP_DEG));
+                point.elevation = Some(elevation);
+                point
+            })
+            .collect();
+        let mut track = Track::new();
+        track.segments = vec![segment];
+        detect_climbs(&Gpx { tracks: vec![track], ..Gpx::default() }, &AnalysisOptions::default())
+    }
+
+    #[test]
+    fn categorises_at_each_score_boundary() {
+        let boundaries = [
+            (80_000.0, ClimbCategory::Hc),
+            (64_000.0, ClimbCategory::Cat1),
+            (32_000.0, ClimbCategory::Cat2),
+            (16_000.0, ClimbCategory::Cat3),
+            (8_000.0, ClimbCategory::Cat4),
+        ];
+        for window in boundaries.windows(2) {
+            let ((upper, harder), (_, easier)) = (window[0], window[1]);
+            assert_eq!(ClimbCategory::from_score(upper), Some(harder));
+            assert_eq!(ClimbCategory::from_score(upper - 0.1), Some(easier));
+        }
+        assert_eq!(ClimbCategory::from_score(8_000.0), Some(ClimbCategory::Cat4));
+        assert_eq!(ClimbCategory::from_score(7_999.9), None);
+    }
+
+    #[test]
+    fn scores_a_climb_by_distance_and_grade() {
+        // 1 km at 8% scores 8,000: category 4
+        let elevations: Vec<f64> = (0..=10).map(|i| 100.0 + i as f64 * 8.0).collect();
+        let found = climbs(&elevations);
+        assert_eq!(found.len(), 1);
+        assert!((found[0].average_grade_pct - 8.0).abs() < 1e-6);
+        assert_eq!(found[0].category, Some(ClimbCategory::Cat4));
+    }
+
+    #[test]
+    fn merges_across_a_dip_shorter_than_the_tolerance() {
+        let found = climbs(&[100.0, 110.0, 120.0, 115.0, 125.0, 135.0, 145.0]);
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].start_index, found[0].end_index), (0, 6));
+        assert!((found[0].gain_m - 45.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn splits_at_a_dip_past_the_tolerance() {
+        let found = climbs(&[100.0, 110.0, 120.0, 130.0, 115.0, 125.0, 135.0, 145.0]);
+        let spans: Vec<(usize, usize)> = found.iter().map(|climb| (climb.start_index, climb.end_index)).collect();
+        assert_eq!(spans, vec![(0, 3), (4, 7)]);
+    }
+
+    #[test]
+    fn lowers_the_foot_to_a_dip_below_the_start() {
+        // The 9 m dip after the first rise ends 4 m below where the climb began
+        let found = climbs(&[100.0, 105.0, 96.0, 110.0, 120.0, 130.0]);
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].start_index, found[0].end_index), (2, 5));
+        assert!((found[0].gain_m - 34.0).abs() < 1e-9);
+    }
+}
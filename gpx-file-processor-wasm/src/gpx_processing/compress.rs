@@ -1,58 +1,324 @@
 // Standard library imports for I/O operations
-use std::io::Write;
+use std::io::{Read, Write};
 
 // External crate imports
-use flate2::{ write::GzEncoder, Compression }; // For gzip compression
-use wasm_bindgen::JsValue; // For WebAssembly <-> JavaScript interop
+use flate2::{
+    read::{DeflateDecoder, GzDecoder, ZlibDecoder},
+    write::{DeflateEncoder, GzEncoder, ZlibEncoder},
+    Compression,
+}; // For gzip, zlib and raw deflate compression
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*; // WebAssembly <-> JavaScript interop
+
+use super::input::{self, MAX_GPX_BYTES};
+use crate::error::{GpxProcessorError, Result};
 
 /**
- * Compresses a GPX file string using gzip compression.
+ * Magic bytes opening the header of every non-gzip compressed payload.
  * 
- * This function takes a GPX XML string, removes excess whitespace to reduce size,
- * and then applies gzip compression with a moderate compression level (6).
- * The compression level balances between compression ratio and processing time.
+ * The high first byte keeps the header from being mistaken for text, in the
+ * same way PNG's signature does. Gzip output carries no extra header: its own
+ * magic bytes already identify it, and stored gzip payloads stay readable by
+ * any gunzip tool.
+ */
+pub const HEADER_MAGIC: [u8; 4] = [0x89, b'M', b'B', b'C'];
+
+/**
+ * Current version of the compression header.
+ */
+const HEADER_VERSION: u8 = 1;
+
+/**
+ * Compression algorithms available for payloads; all are pure Rust.
+ */
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionCodec {
+    /**
+     * Gzip (RFC 1952), readable by any gunzip tool.
+     */
+    Gzip,
+    /**
+     * Raw deflate (RFC 1951) with no framing.
+     */
+    Deflate,
+    /**
+     * Zlib (RFC 1950).
+     */
+    Zlib,
+    /**
+     * Brotli (RFC 7932); usually smallest, slowest to compress.
+     */
+    Brotli,
+}
+
+impl CompressionCodec {
+    /**
+     * Identifier stored in the compression header.
+     */
+    fn id(self) -> u8 {
+        match self {
+            CompressionCodec::Gzip => 0,
+            CompressionCodec::Deflate => 1,
+            CompressionCodec::Zlib => 2,
+            CompressionCodec::Brotli => 3,
+        }
+    }
+
+    /**
+     * Codec for a compression header identifier.
+     */
+    fn from_id(id: u8) -> Option<CompressionCodec> {
+        match id {
+            0 => Some(CompressionCodec::Gzip),
+            1 => Some(CompressionCodec::Deflate),
+            2 => Some(CompressionCodec::Zlib),
+            3 => Some(CompressionCodec::Brotli),
+            _ => None,
+        }
+    }
+
+    /**
+     * Highest compression level the codec accepts.
+     */
+    fn max_level(self) -> u32 {
+        match self {
+            CompressionCodec::Brotli => 11,
+            _ => 9,
+        }
+    }
+}
+
+/**
+ * Codec and level used to compress payloads.
  * 
- * @param data - The GPX file content as a string
- * @returns A Result containing either the compressed bytes or a JavaScript error value
+ * The defaults (gzip at level 6) match the compression the site has always
+ * used, so default payloads stay plain `.gz` files.
  */
-pub fn compress_gpx(data: &str) -> Result<Vec<u8>, JsValue> {
-    // Initialize a gzip encoder with compression level 6 (moderate)
-    // Level range is 0-9: 0=no compression, 9=max compression
-    let mut encoder = GzEncoder::new(Vec::new(), Compression::new(6));
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompressionOptions {
+    codec: CompressionCodec, // Compression algorithm
+    level: u32,              // Compression level, clamped to the codec's range
+}
+
+impl Default for CompressionOptions {
+    fn default() -> Self {
+        CompressionOptions {
+            codec: CompressionCodec::Gzip,
+            level: 6,
+        }
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl CompressionOptions {
+    /**
+     * Creates options for gzip at level 6.
+     */
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new() -> CompressionOptions {
+        CompressionOptions::default()
+    }
 
-    // Pre-process the data to remove unnecessary whitespace
-    let cleaned_data = remove_excess_whitespace(data);
+    /**
+     * Selects the compression algorithm.
+     */
+    pub fn set_codec(&mut self, codec: CompressionCodec) {
+        self.codec = codec;
+    }
 
-    // Write the cleaned data to the encoder
-    // Convert any IO errors to JavaScript errors for proper error handling in the browser
-    encoder
-        .write_all(cleaned_data.as_bytes())
-        .map_err(|e| JsValue::from_str(&format!("Compression error: {}", e)))?;
+    /**
+     * Sets the compression level: 0-9 for gzip, deflate and zlib, 0-11 for brotli.
+     */
+    pub fn set_level(&mut self, level: u32) {
+        self.level = level;
+    }
 
-    // Finalize the compression and return the compressed bytes
-    // This consumes the encoder and returns the compressed data
-    encoder.finish().map_err(|e| JsValue::from_str(&format!("Compression error: {}", e)))
+    /**
+     * The configured compression algorithm.
+     */
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn codec(&self) -> CompressionCodec {
+        self.codec
+    }
+
+    /**
+     * The compression level that will be used, after clamping to the codec's range.
+     */
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn level(&self) -> u32 {
+        self.level.min(self.codec.max_level())
+    }
 }
 
 /**
- * Removes excess whitespace from XML content to reduce file size.
+ * Compresses raw bytes with the codec and level in `options`.
  * 
- * This function:
- * 1. Splits the input string into lines
- * 2. Trims whitespace from each line
- * 3. Filters out empty lines
- * 4. Rejoins the lines with newline characters
+ * Non-gzip output starts with a six-byte header (`HEADER_MAGIC`, a version
+ * byte and the codec identifier) so `decompress_bytes` can pick the decoder
+ * without being told; gzip output is a plain gzip stream.
  * 
- * While this is not a full XML minification, it significantly reduces
- * file size while maintaining XML structure and readability.
+ * @param data - The bytes to compress
+ * @param options - Codec and level to use
+ * @returns A Result containing either the compressed bytes or a `Compression` error
+ */
+pub fn compress_bytes(data: &[u8], options: &CompressionOptions) -> Result<Vec<u8>> {
+    let codec = options.codec();
+    let level = options.level();
+
+    let mut output = Vec::new();
+    if codec != CompressionCodec::Gzip {
+        output.extend_from_slice(&HEADER_MAGIC);
+        output.extend_from_slice(&[HEADER_VERSION, codec.id()]);
+    }
+
+    // Convert any IO errors to typed errors for proper error handling in the browser
+    let to_error = |e: std::io::Error| GpxProcessorError::Compression(e.to_string());
+    match codec {
+        CompressionCodec::Gzip => {
+            let mut encoder = GzEncoder::new(output, Compression::new(level));
+            encoder.write_all(data).map_err(to_error)?;
+            encoder.finish().map_err(to_error)
+        }
+        CompressionCodec::Deflate => {
+            let mut encoder = DeflateEncoder::new(output, Compression::new(level));
+            encoder.write_all(data).map_err(to_error)?;
+            encoder.finish().map_err(to_error)
+        }
+        CompressionCodec::Zlib => {
+            let mut encoder = ZlibEncoder::new(output, Compression::new(level));
+            encoder.write_all(data).map_err(to_error)?;
+            encoder.finish().map_err(to_error)
+        }
+        CompressionCodec::Brotli => {
+            // 4KB buffer and a 4MB window (lgwin 22), brotli's usual defaults
+            let mut encoder = brotli::CompressorWriter::new(output, 4096, level, 22);
+            encoder.write_all(data).map_err(to_error)?;
+            encoder.flush().map_err(to_error)?;
+            Ok(encoder.into_inner())
+        }
+    }
+}
+
+/**
+ * Identifies the codec of compressed bytes from their header.
+ * 
+ * @param data - Possibly compressed bytes
+ * @returns The codec, or None if the bytes carry no recognised compression header
+ */
+pub fn detect_codec(data: &[u8]) -> Option<CompressionCodec> {
+    if input::is_gzip(data) {
+        return Some(CompressionCodec::Gzip);
+    }
+    match data.strip_prefix(&HEADER_MAGIC)? {
+        [HEADER_VERSION, id, ..] => CompressionCodec::from_id(*id),
+        _ => None,
+    }
+}
+
+/**
+ * Decompresses bytes produced by `compress_bytes`, detecting the codec from the header.
  * 
- * @param xml - The XML string to process
- * @returns A new string with excess whitespace removed
+ * Decompression stops one byte past the 50MB cap, so a malicious payload
+ * cannot inflate without bound.
+ * 
+ * @param data - The compressed bytes
+ * @returns A Result containing the decompressed bytes, or a `Decompression` or `TooLarge` error
  */
-fn remove_excess_whitespace(xml: &str) -> String {
-    xml.lines()                         // Split into lines
-        .map(|line| line.trim())        // Remove leading/trailing whitespace
-        .filter(|line| !line.is_empty()) // Remove empty lines
-        .collect::<Vec<_>>()            // Collect into a vector
-        .join("\n")                     // Join with newlines
-}
\ No newline at end of file
+pub fn decompress_bytes(data: &[u8]) -> Result<Vec<u8>> {
+    let codec = detect_codec(data)
+        .ok_or_else(|| GpxProcessorError::Decompression("unrecognised compression header".to_string()))?;
+    let body = match codec {
+        CompressionCodec::Gzip => data,
+        _ => &data[HEADER_MAGIC.len() + 2..],
+    };
+
+    let reader: Box<dyn Read + '_> = match codec {
+        CompressionCodec::Gzip => Box::new(GzDecoder::new(body)),
+        CompressionCodec::Deflate => Box::new(DeflateDecoder::new(body)),
+        CompressionCodec::Zlib => Box::new(ZlibDecoder::new(body)),
+        CompressionCodec::Brotli => Box::new(brotli::Decompressor::new(body, 4096)),
+    };
+
+    let mut decompressed = Vec::new();
+    reader
+        .take(MAX_GPX_BYTES as u64 + 1)
+        .read_to_end(&mut decompressed)
+        .map_err(|e| GpxProcessorError::Decompression(e.to_string()))?;
+
+    input::check_size(decompressed.len())?;
+    Ok(decompressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODECS: [CompressionCodec; 4] = [
+        CompressionCodec::Gzip,
+        CompressionCodec::Deflate,
+        CompressionCodec::Zlib,
+        CompressionCodec::Brotli,
+    ];
+
+    fn options(codec: CompressionCodec, level: u32) -> CompressionOptions {
+        let mut options = CompressionOptions::new();
+        options.set_codec(codec);
+        options.set_level(level);
+        options
+    }
+
+    fn sample() -> Vec<u8> {
+        (0..2000).flat_map(|i: u32| format!("{{\"lat\":42.{:05},\"lon\":-85.{:05}}}", i * 7, i * 3).into_bytes()).collect()
+    }
+
+    #[test]
+    fn round_trips_every_codec() {
+        let data = sample();
+        for codec in CODECS {
+            let compressed = compress_bytes(&data, &options(codec, 6)).unwrap();
+            assert!(compressed.len() < data.len(), "{:?} did not shrink the data", codec);
+            assert_eq!(detect_codec(&compressed), Some(codec));
+            assert_eq!(decompress_bytes(&compressed).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn gzip_output_is_a_plain_gzip_stream() {
+        let compressed = compress_bytes(&sample(), &CompressionOptions::default()).unwrap();
+        assert_eq!(compressed[..2], [0x1f, 0x8b]);
+        assert!(!compressed.starts_with(&HEADER_MAGIC));
+
+        let zlib = compress_bytes(&sample(), &options(CompressionCodec::Zlib, 6)).unwrap();
+        assert_eq!(zlib[..6], [0x89, b'M', b'B', b'C', HEADER_VERSION, 2]);
+    }
+
+    #[test]
+    fn rejects_unknown_header_versions_and_codecs() {
+        let mut compressed = compress_bytes(&sample(), &options(CompressionCodec::Brotli, 6)).unwrap();
+        compressed[4] = HEADER_VERSION + 1;
+        assert!(matches!(decompress_bytes(&compressed), Err(GpxProcessorError::Decompression(_))));
+
+        compressed[4] = HEADER_VERSION;
+        compressed[5] = 4;
+        assert!(matches!(decompress_bytes(&compressed), Err(GpxProcessorError::Decompression(_))));
+
+        assert!(matches!(decompress_bytes(b"plain text"), Err(GpxProcessorError::Decompression(_))));
+    }
+
+    #[test]
+    fn clamps_the_level_to_the_codec_range() {
+        assert_eq!(options(CompressionCodec::Gzip, 20).level(), 9);
+        assert_eq!(options(CompressionCodec::Deflate, 20).level(), 9);
+        assert_eq!(options(CompressionCodec::Zlib, 20).level(), 9);
+        assert_eq!(options(CompressionCodec::Brotli, 20).level(), 11);
+        assert_eq!(options(CompressionCodec::Brotli, 4).level(), 4);
+
+        let data = sample();
+        for codec in CODECS {
+            let compressed = compress_bytes(&data, &options(codec, 20)).unwrap();
+            assert_eq!(decompress_bytes(&compressed).unwrap(), data);
+        }
+    }
+}
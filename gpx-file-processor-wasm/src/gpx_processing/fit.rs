@@ -0,0 +1,589 @@
+//! FIT Decoding Module
+//!
+//! Garmin, Wahoo and most other bike computers record rides as FIT files, a
+//! compact binary format, and only export GPX on request. This module decodes
+//! a FIT activity into the same `Gpx` value a parsed GPX file produces, so the
+//! rest of the pipeline never needs to know which format was uploaded:
+//! - Validates the file header and CRC, including chained files
+//! - Follows definition messages, both byte orders, developer fields and compressed timestamps
+//! - Maps `record` messages (position, altitude, time) to track points
+//! - Keeps each point's heart rate, cadence and power, which GPX points have no
+//!   place for, as sensor samples alongside the track
+//! - Takes the sport and device manufacturer as the activity type and creator
+//!
+//! Only the messages above are interpreted; everything else is skipped using
+//! its definition, so files from newer devices still decode.
+
+use geo_types::Point;  // Coordinates of a GPX point
+use gpx::{Gpx, GpxVersion, Track, TrackSegment, Waypoint};  // GPX parsing and representation
+use serde::{Deserialize, Serialize};  // Serialization framework
+
+use super::{input::{self, MAX_GPX_POINTS}, payload::to_time};
+use crate::error::{GpxProcessorError, Result};
+
+/// Seconds between the Unix epoch and the FIT epoch (1989-12-31T00:00:00Z).
+const FIT_EPOCH_OFFSET: i64 = 631_065_600;
+
+/// Degrees per semicircle, the FIT unit for latitude and longitude.
+const DEGREES_PER_SEMICIRCLE: f64 = 180.0 / 2_147_483_648.0;
+
+// Global message numbers of the messages this decoder reads
+const MESG_FILE_ID: u16 = 0;
+const MESG_SPORT: u16 = 12;
+const MESG_SESSION: u16 = 18;
+const MESG_RECORD: u16 = 20;
+
+/// Field number of the timestamp, shared by every message type.
+const FIELD_TIMESTAMP: u8 = 253;
+
+/// Nibble lookup table for the FIT CRC-16.
+const CRC_TABLE: [u16; 16] = [
+    0x0000, 0xCC01, 0xD801, 0x1400, 0xF001, 0x3C00, 0x2800, 0xE401,
+    0xA001, 0x6C00, 0x7800, 0xB401, 0x5000, 0x9C01, 0x8801, 0x4400,
+];
+
+/// A decoded FIT activity: the ride as GPX plus the sensor data GPX cannot hold.
+#[derive(Debug)]
+pub struct FitActivity {
+    pub gpx: Gpx,                // Positions, altitudes and times as a single-track GPX file
+    pub samples: SensorSamples,  // Heart rate, cadence and power of each track point
+}
+
+/// Heart rate, cadence and power recorded with one track point.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct SensorSample {
+    pub heart_rate_bpm: Option<u16>,  // Heart rate in beats per minute
+    pub cadence_rpm: Option<u16>,     // Pedalling cadence in revolutions per minute
+    pub power_w: Option<u16>,         // Power in watts
+}
+
+/// Sensor samples of every track point, grouped by track and segment like `Gpx::tracks`.
+pub type SensorSamples = Vec<Vec<Vec<SensorSample>>>;
+
+/// Average and maximum of one sensor channel over a ride.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SensorStats {
+    pub average: f64,  // Mean of every recorded sample
+    pub max: f64,      // Highest recorded sample
+}
+
+/// Sensor channels recorded alongside the positions in a FIT file.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SensorSummary {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heart_rate_bpm: Option<SensorStats>,  // Heart rate in beats per minute
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cadence_rpm: Option<SensorStats>,     // Pedalling cadence in revolutions per minute
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub power_w: Option<SensorStats>,         // Power in watts
+}
+
+impl SensorSummary {
+    /// Summarizes sensor samples, or returns `None` if none of them has a value.
+    ///
+    /// Callers pass the samples of the points they describe, so a cropped
+    /// document is summarized from what is left of the ride.
+    pub fn from_samples<'a>(samples: impl IntoIterator<Item = &'a SensorSample>) -> Option<SensorSummary> {
+        let (mut heart_rate, mut cadence, mut power) = (SensorTotal::default(), SensorTotal::default(), SensorTotal::default());
+        for sample in samples {
+            heart_rate.add(sample.heart_rate_bpm);
+            cadence.add(sample.cadence_rpm);
+            power.add(sample.power_w);
+        }
+
+        let summary = SensorSummary {
+            heart_rate_bpm: heart_rate.stats(),
+            cadence_rpm: cadence.stats(),
+            power_w: power.stats(),
+        };
+        let has_sensors = summary.heart_rate_bpm.is_some() || summary.cadence_rpm.is_some() || summary.power_w.is_some();
+        has_sensors.then_some(summary)
+    }
+}
+
+/// Returns true if the bytes start with a FIT file header.
+pub fn is_fit(data: &[u8]) -> bool {
+    data.len() >= 12 && matches!(data[0], 12 | 14) && &data[8..12] == b".FIT"
+}
+
+/// Decodes a FIT activity file into a GPX structure and a sensor summary.
+///
+/// Records without a position (before the GPS has a fix, or on an indoor
+/// trainer) add no track point, so their sensor values are not kept either.
+///
+/// # Arguments
+/// * `data` - The FIT file bytes
+///
+/// # Returns
+/// * `Result<FitActivity>` - The decoded activity or an error
+///
+/// # Errors
+/// * `TooLarge` if the file exceeds 50MB
+/// * `TooManyPoints` if the file has more than [`MAX_GPX_POINTS`] positioned records
+/// * `ParseError` if the file is truncated, fails its CRC check or is otherwise malformed
+pub fn decode_fit(data: &[u8]) -> Result<FitActivity> {
+    input::check_size(data.len())?; // 50MB max
+
+    let mut decoder = Decoder::default();
+    let mut rest = data;
+
+    // A FIT file may hold several chained files back to back, each with its own header and CRC
+    while !rest.is_empty() {
+        if !is_fit(rest) {
+            return Err(parse_error("missing FIT file header"));
+        }
+        let header_size = rest[0] as usize;
+        let data_size = u32::from_le_bytes([rest[4], rest[5], rest[6], rest[7]]) as usize;
+        // Checked, as a huge data size overflows `usize` on wasm32
+        let end = header_size.checked_add(data_size)
+            .and_then(|end| end.checked_add(2))
+            .filter(|&end| end <= rest.len())
+            .ok_or_else(|| parse_error("FIT file is truncated"))?;
+
+        // Running the CRC over the records and their little-endian CRC leaves zero
+        let stored_crc = u16::from_le_bytes([rest[end - 2], rest[end - 1]]);
+        if stored_crc != 0 && crc16(&rest[..end]) != 0 {
+            return Err(parse_error("FIT file CRC does not match"));
+        }
+
+        decoder.read_records(&rest[header_size..header_size + data_size])?;
+        rest = &rest[end..];
+    }
+
+    decoder.finish()
+}
+
+/// Computes the FIT CRC-16 of the bytes.
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0, |crc, &byte| {
+        let crc = (crc >> 4) ^ CRC_TABLE[(crc & 0xF) as usize] ^ CRC_TABLE[(byte & 0xF) as usize];
+        (crc >> 4) ^ CRC_TABLE[(crc & 0xF) as usize] ^ CRC_TABLE[(byte >> 4) as usize]
+    })
+}
+
+/// Builds the error for a malformed FIT file.
+fn parse_error(message: &str) -> GpxProcessorError {
+    GpxProcessorError::ParseError { line: None, message: message.to_string() }
+}
+
+/// One field of a definition message.
+#[derive(Debug, Clone, Copy)]
+struct FieldDefinition {
+    number: u8,     // Field number within the message, e.g. 0 for position_lat
+    size: usize,    // Size of the field in bytes
+    base_type: u8,  // FIT base type, which fixes the width, sign and invalid value
+}
+
+/// Layout of the data messages that follow a definition for the same local type.
+#[derive(Debug, Clone)]
+struct Definition {
+    little_endian: bool,           // Byte order of multi-byte fields
+    global: u16,                   // Global message number, e.g. 20 for record
+    fields: Vec<FieldDefinition>,  // Fields in the order they appear
+    developer_size: usize,         // Bytes of developer fields after the regular fields
+}
+
+/// Running total of one sensor channel.
+#[derive(Debug, Default)]
+struct SensorTotal {
+    sum: f64,
+    count: usize,
+    max: f64,
+}
+
+impl SensorTotal {
+    fn add(&mut self, value: Option<u16>) {
+        if let Some(value) = value.map(f64::from) {
+            self.sum += value;
+            self.count += 1;
+            self.max = self.max.max(value);
+        }
+    }
+
+    fn stats(&self) -> Option<SensorStats> {
+        (self.count > 0).then(|| SensorStats { average: self.sum / self.count as f64, max: self.max })
+    }
+}
+
+/// Decoder state carried across records and chained files.
+#[derive(Debug, Default)]
+struct Decoder {
+    definitions: [Option<Definition>; 16],  // Current definition for each local message type
+    last_timestamp: Option<u32>,            // Base for compressed timestamp headers
+    points: Vec<Waypoint>,                  // Track points, up to the point limit
+    samples: Vec<SensorSample>,             // Sensor values of each track point
+    point_count: usize,                     // Positioned records seen, including any past the limit
+    sport: Option<&'static str>,            // Activity type from the session or sport message
+    manufacturer: Option<&'static str>,     // Device manufacturer from the file_id message
+}
+
+impl Decoder {
+    /// Reads the records between a file header and its CRC.
+    fn read_records(&mut self, data: &[u8]) -> Result<()> {
+        // Local message types only hold within one file of a chain
+        self.definitions = Default::default();
+        let mut position = 0;
+
+        while position < data.len() {
+            let header = data[position];
+            position += 1;
+
+            if header & 0x80 != 0 {
+                // Compressed timestamp header: a 5-bit offset from the last full timestamp
+                let local = ((header >> 5) & 0x03) as usize;
+                let offset = u32::from(header & 0x1F);
+                let last = self.last_timestamp
+                    .ok_or_else(|| parse_error("compressed timestamp before any full timestamp"))?;
+                let mut timestamp = (last & !0x1F) + offset;
+                if offset < last & 0x1F {
+                    timestamp += 0x20;
+                }
+                self.last_timestamp = Some(timestamp);
+                position = self.read_data(data, position, local, Some(timestamp))?;
+            } else if header & 0x40 != 0 {
+                let has_developer_fields = header & 0x20 != 0;
+                position = self.read_definition(data, position, (header & 0x0F) as usize, has_developer_fields)?;
+            } else {
+                position = self.read_data(data, position, (header & 0x0F) as usize, None)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads a definition message starting after its header, returning the position after it.
+    fn read_definition(&mut self, data: &[u8], position: usize, local: usize, has_developer_fields: bool) -> Result<usize> {
+        let fixed = take(data, position, 5)?;
+        let little_endian = fixed[1] == 0;
+        let global = if little_endian {
+            u16::from_le_bytes([fixed[2], fixed[3]])
+        } else {
+            u16::from_be_bytes([fixed[2], fixed[3]])
+        };
+        let field_count = fixed[4] as usize;
+        let mut position = position + 5;
+
+        let fields = take(data, position, field_count * 3)?
+            .as_chunks::<3>().0.iter()
+            .map(|&[number, size, base_type]| FieldDefinition { number, size: size as usize, base_type })
+            .collect();
+        position += field_count * 3;
+
+        let mut developer_size = 0;
+        if has_developer_fields {
+            let developer_count = take(data, position, 1)?[0] as usize;
+            position += 1;
+            developer_size = take(data, position, developer_count * 3)?
+                .as_chunks::<3>().0.iter()
+                .map(|&[_, size, _]| size as usize)
+                .sum();
+            position += developer_count * 3;
+        }
+
+        self.definitions[local] = Some(Definition { little_endian, global, fields, developer_size });
+        Ok(position)
+    }
+
+    /// Reads a data message starting after its header, returning the position after it.
+    fn read_data(&mut self, data: &[u8], mut position: usize, local: usize, timestamp: Option<u32>) -> Result<usize> {
+        let definition = self.definitions[local]
+            .take()
+            .ok_or_else(|| parse_error("data message without a definition"))?;
+
+        let mut message = Message { timestamp, ..Message::default() };
+        for field in &definition.fields {
+            let bytes = take(data, position, field.size)?;
+            position += field.size;
+            if let Some(value) = field_value(bytes, field.base_type, definition.little_endian) {
+                message.set(field.number, value);
+            }
+        }
+        take(data, position, definition.developer_size)?;
+        position += definition.developer_size;
+
+        if let Some(timestamp) = message.timestamp {
+            self.last_timestamp = Some(timestamp);
+        }
+        self.apply(definition.global, &message);
+
+        self.definitions[local] = Some(definition);
+        Ok(position)
+    }
+
+    /// Applies a decoded message to the activity.
+    fn apply(&mut self, global: u16, message: &Message) {
+        match global {
+            MESG_RECORD => self.add_record(message),
+            MESG_FILE_ID => self.manufacturer = self.manufacturer.or(message.field(1).and_then(manufacturer_name)),
+            MESG_SPORT => self.sport = self.sport.or(message.field(0).and_then(sport_name)),
+            MESG_SESSION => self.sport = self.sport.or(message.field(5).and_then(sport_name)),
+            _ => {}
+        }
+    }
+
+    /// Adds a record message as a track point and its sensor sample.
+    fn add_record(&mut self, message: &Message) {
+        let (Some(lat), Some(lon)) = (message.field(0), message.field(1)) else {
+            return;
+        };
+
+        // Keep counting past the limit so the error reports the real total
+        self.point_count += 1;
+        if self.points.len() >= MAX_GPX_POINTS {
+            return;
+        }
+
+        let mut point = Waypoint::new(Point::new(lon * DEGREES_PER_SEMICIRCLE, lat * DEGREES_PER_SEMICIRCLE));
+        // Enhanced altitude supersedes the 16-bit field, which tops out at 12,607 m
+        point.elevation = message.field(78).or(message.field(2)).map(|altitude| altitude / 5.0 - 500.0);
+        point.time = message.timestamp.and_then(|timestamp| to_time(i64::from(timestamp) + FIT_EPOCH_OFFSET));
+        self.points.push(point);
+        self.samples.push(SensorSample {
+            heart_rate_bpm: message.field(3).map(|value| value as u16),
+            cadence_rpm: message.field(4).map(|value| value as u16),
+            power_w: message.field(7).map(|value| value as u16),
+        });
+    }
+
+    /// Builds the activity once every record has been read.
+    fn finish(self) -> Result<FitActivity> {
+        if self.point_count > MAX_GPX_POINTS {
+            return Err(GpxProcessorError::TooManyPoints { count: self.point_count, max: MAX_GPX_POINTS });
+        }
+
+        let mut gpx = Gpx {
+            version: GpxVersion::Gpx11,
+            creator: self.manufacturer.map(str::to_string),
+            ..Gpx::default()
+        };
+        let mut samples = Vec::new();
+        if !self.points.is_empty() {
+            samples.push(vec![self.samples]);
+            let mut segment = TrackSegment::new();
+            segment.points = self.points;
+            let mut track = Track::new();
+            track.type_ = self.sport.map(str::to_string);
+            track.segments = vec![segment];
+            gpx.tracks.push(track);
+        }
+
+        Ok(FitActivity { gpx, samples })
+    }
+}
+
+/// The fields of one data message this decoder reads, as raw numbers.
+#[derive(Debug, Default)]
+struct Message {
+    timestamp: Option<u32>,       // Seconds since the FIT epoch
+    fields: [Option<f64>; 8],     // Fields 0-7, which cover position, altitude and sensors
+    enhanced_altitude: Option<f64>,
+}
+
+impl Message {
+    fn set(&mut self, number: u8, value: f64) {
+        match number {
+            0..=7 => self.fields[number as usize] = Some(value),
+            78 => self.enhanced_altitude = Some(value),
+            FIELD_TIMESTAMP => self.timestamp = Some(value as u32),
+            _ => {}
+        }
+    }
+
+    fn field(&self, number: u8) -> Option<f64> {
+        match number {
+            0..=7 => self.fields[number as usize],
+            78 => self.enhanced_altitude,
+            _ => None,
+        }
+    }
+}
+
+/// Returns `len` bytes at `position`, or an error if the records end first.
+fn take(data: &[u8], position: usize, len: usize) -> Result<&[u8]> {
+    data.get(position..position + len).ok_or_else(|| parse_error("FIT record runs past the end of the file"))
+}
+
+/// Reads the first value of a numeric field, or `None` if it holds the base type's invalid value.
+///
+/// Strings and fields too short for their base type also return `None`.
+fn field_value(bytes: &[u8], base_type: u8, little_endian: bool) -> Option<f64> {
+    fn array<const N: usize>(bytes: &[u8], little_endian: bool) -> Option<[u8; N]> {
+        let mut array: [u8; N] = bytes.get(..N)?.try_into().ok()?;
+        if !little_endian {
+            array.reverse();
+        }
+        Some(array)
+    }
+
+    match base_type & 0x1F {
+        0x00 | 0x02 | 0x0D => bytes.first().filter(|&&value| value != 0xFF).map(|&value| f64::from(value)),
+        0x01 => bytes.first().map(|&value| value as i8).filter(|&value| value != i8::MAX).map(f64::from),
+        0x0A => bytes.first().filter(|&&value| value != 0).map(|&value| f64::from(value)),
+        0x03 => array(bytes, little_endian).map(i16::from_le_bytes).filter(|&value| value != i16::MAX).map(f64::from),
+        0x04 => array(bytes, little_endian).map(u16::from_le_bytes).filter(|&value| value != u16::MAX).map(f64::from),
+        0x0B => array(bytes, little_endian).map(u16::from_le_bytes).filter(|&value| value != 0).map(f64::from),
+        0x05 => array(bytes, little_endian).map(i32::from_le_bytes).filter(|&value| value != i32::MAX).map(f64::from),
+        0x06 => array(bytes, little_endian).map(u32::from_le_bytes).filter(|&value| value != u32::MAX).map(f64::from),
+        0x0C => array(bytes, little_endian).map(u32::from_le_bytes).filter(|&value| value != 0).map(f64::from),
+        0x08 => array(bytes, little_endian).map(f32::from_le_bytes).filter(|value| value.is_finite()).map(f64::from),
+        0x09 => array(bytes, little_endian).map(f64::from_le_bytes).filter(|value| value.is_finite()),
+        0x0E => array(bytes, little_endian).map(i64::from_le_bytes).filter(|&value| value != i64::MAX).map(|value| value as f64),
+        0x0F => array(bytes, little_endian).map(u64::from_le_bytes).filter(|&value| value != u64::MAX).map(|value| value as f64),
+        0x10 => array(bytes, little_endian).map(u64::from_le_bytes).filter(|&value| value != 0).map(|value| value as f64),
+        _ => None,
+    }
+}
+
+/// Activity type written for a FIT sport, for the sports riders upload.
+fn sport_name(sport: f64) -> Option<&'static str> {
+    match sport as u8 {
+        1 => Some("running"),
+        2 => Some("cycling"),
+        5 => Some("swimming"),
+        11 => Some("walking"),
+        17 => Some("hiking"),
+        _ => None,
+    }
+}
+
+/// Creator written for a FIT manufacturer, for the common device makers.
+fn manufacturer_name(manufacturer: f64) -> Option<&'static str> {
+    match manufacturer as u16 {
+        1 => Some("Garmin"),
+        23 => Some("Suunto"),
+        32 => Some("Wahoo Fitness"),
+        123 => Some("Polar"),
+        260 => Some("Zwift"),
+        265 => Some("Strava"),
+        289 => Some("Hammerhead"),
+        294 => Some("COROS"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpx_processing::reduce::{self, ReduceOptions};
+
+    /// Wraps records in a 14-byte header and a trailing CRC.
+    fn fit_file(records: &[u8]) -> Vec<u8> {
+        let mut file = vec![14, 0x20, 0x54, 0x08];
+        file.extend_from_slice(&(records.len() as u32).to_le_bytes());
+        file.extend_from_slice(b".FIT");
+        file.extend_from_slice(&[0, 0]);
+        file.extend_from_slice(records);
+        let crc = crc16(&file);
+        file.extend_from_slice(&crc.to_le_bytes());
+        file
+    }
+
+    /// A Garmin file_id, then a record with position, altitude, heart rate and power.
+    fn activity_records() -> Vec<u8> {
+        let mut records = vec![
+            0x40, 0, 0, 0, 0, 1, 1, 2, 0x84,  // file_id definition: manufacturer
+            0x00, 1, 0,                      // Garmin
+            // record definition with one developer field of 2 bytes
+            0x62, 0, 0, 20, 0, 6, 253, 4, 0x86, 0, 4, 0x85, 1, 4, 0x85, 2, 2, 0x84, 3, 1, 0x02, 7, 2, 0x84, 1, 0, 2, 0,
+            0x02,
+        ];
+        records.extend_from_slice(&1_000_000_000u32.to_le_bytes());         // timestamp
+        records.extend_from_slice(&536_870_912i32.to_le_bytes());           // 45 degrees
+        records.extend_from_slice(&(-1_431_655_765i32).to_le_bytes());      // -120 degrees
+        records.extend_from_slice(&3_000u16.to_le_bytes());                 // 100 m
+        records.push(150);                                                  // heart rate
+        records.extend_from_slice(&250u16.to_le_bytes());                   // power
+        records.extend_from_slice(&[0xAB, 0xCD]);                           // developer field
+        records
+    }
+
+    #[test]
+    fn decodes_a_minimal_activity() {
+        let activity = decode_fit(&fit_file(&activity_records())).unwrap();
+        assert_eq!(activity.gpx.creator.as_deref(), Some("Garmin"));
+
+        let points = &activity.gpx.tracks[0].segments[0].points;
+        assert_eq!(points.len(), 1);
+        assert!((points[0].point().y() - 45.0).abs() < 1e-6);
+        assert!((points[0].point().x() + 120.0).abs() < 1e-6);
+        assert_eq!(points[0].elevation, Some(100.0));
+        assert!(points[0].time.is_some());
+
+        let sample = SensorSample { heart_rate_bpm: Some(150), cadence_rpm: None, power_w: Some(250) };
+        assert_eq!(activity.samples, vec![vec![vec![sample]]]);
+
+        let sensors = SensorSummary::from_samples(activity.samples.iter().flatten().flatten()).unwrap();
+        assert_eq!(sensors.heart_rate_bpm, Some(SensorStats { average: 150.0, max: 150.0 }));
+        assert_eq!(sensors.power_w, Some(SensorStats { average: 250.0, max: 250.0 }));
+        assert_eq!(sensors.cadence_rpm, None);
+    }
+
+    #[test]
+    fn keeps_sensor_values_through_reduction() {
+        let activity = decode_fit(&fit_file(&activity_records())).unwrap();
+        let (reduced, _report) = reduce::reduce_gpx(&activity.gpx, Some(&activity.samples), &ReduceOptions::default());
+
+        let point = &reduced.trk[0].trkseg[0].trkpt[0];
+        assert_eq!((point.hr, point.cad, point.pwr), (Some(150), None, Some(250)));
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let file = fit_file(&activity_records());
+        for end in 1..file.len() {
+            assert!(matches!(decode_fit(&file[..end]), Err(GpxProcessorError::ParseError { .. })));
+        }
+    }
+
+    #[test]
+    fn rejects_a_bad_crc() {
+        let mut file = fit_file(&activity_records());
+        file[20] ^= 0x01;
+        assert!(matches!(decode_fit(&file), Err(GpxProcessorError::ParseError { message, .. }) if message.contains("CRC")));
+    }
+
+    #[test]
+    fn rejects_a_data_size_past_the_end() {
+        // Header size plus a u32::MAX data size overflows usize on wasm32
+        let mut file = vec![14, 0x20, 0x54, 0x08, 0xFF, 0xFF, 0xFF, 0xFF];
+        file.extend_from_slice(b".FIT");
+        file.extend_from_slice(&[0, 0, 0]);
+        assert!(matches!(decode_fit(&file), Err(GpxProcessorError::ParseError { message, .. }) if message.contains("truncated")));
+    }
+
+    #[test]
+    fn decodes_compressed_timestamps() {
+        let mut records = activity_records();
+        // record definition with a position and heart rate, sent with a compressed timestamp 5 s later
+        records.extend_from_slice(&[0x43, 0, 0, 20, 0, 3, 0, 4, 0x85, 1, 4, 0x85, 3, 1, 0x02]);
+        let offset = (1_000_000_005u32 & 0x1F) as u8;
+        records.push(0x80 | (3 << 5) | offset);
+        records.extend_from_slice(&536_870_912i32.to_le_bytes());
+        records.extend_from_slice(&(-1_431_655_765i32).to_le_bytes());
+        records.push(170);
+
+        let activity = decode_fit(&fit_file(&records)).unwrap();
+        let points = &activity.gpx.tracks[0].segments[0].points;
+        let seconds = |index: usize| time::OffsetDateTime::from(points[index].time.unwrap()).unix_timestamp();
+        assert_eq!(seconds(1) - seconds(0), 5);
+        assert_eq!(activity.samples[0][0][1].heart_rate_bpm, Some(170));
+
+        let sensors = SensorSummary::from_samples(activity.samples.iter().flatten().flatten()).unwrap();
+        assert_eq!(sensors.heart_rate_bpm, Some(SensorStats { average: 160.0, max: 170.0 }));
+
+        // A compressed timestamp needs a full one before it
+        let records = [0x43, 0, 0, 20, 0, 1, 3, 1, 0x02, 0x80 | (3 << 5), 170];
+        assert!(decode_fit(&fit_file(&records)).is_err());
+    }
+
+    #[test]
+    fn decodes_big_endian_definitions() {
+        let mut records = activity_records();
+        // Big-endian session definition with the sport field, set to cycling; read
+        // little-endian, the global message number would be 4608 instead of 18
+        records.extend_from_slice(&[0x41, 0, 1, 0, 18, 1, 5, 1, 0x00, 0x01, 2]);
+
+        let activity = decode_fit(&fit_file(&records)).unwrap();
+        assert_eq!(activity.gpx.tracks[0].type_.as_deref(), Some("cycling"));
+    }
+}
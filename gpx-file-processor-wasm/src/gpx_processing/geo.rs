@@ -0,0 +1,42 @@
+//! Geodesic Helpers Module
+//!
+//! Small distance helpers shared by the reduction and analysis code.
+
+/// Mean Earth radius in metres (IUGG).
+const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+/// Great-circle distance in metres between two `(lat, lon)` pairs using the haversine formula.
+///
+/// # Arguments
+/// * `a` - First point as `(lat, lon)` in decimal degrees
+/// * `b` - Second point as `(lat, lon)` in decimal degrees
+///
+/// # Returns
+/// * `f64` - The distance between the points in metres
+pub fn haversine_m(a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (lat1, lat2) = (a.0.to_radians(), b.0.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lon = (b.1 - a.1).to_radians();
+
+    let h = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * h.sqrt().min(1.0).asin()
+}
+
+/// Running haversine distance in metres along a polyline of `(lat, lon)` pairs.
+///
+/// # Arguments
+/// * `points` - The polyline as `(lat, lon)` pairs in decimal degrees
+///
+/// # Returns
+/// * `Vec<f64>` - Distance from the first point to each point; `0.0` for the first
+pub fn cumulative_distances_m(points: &[(f64, f64)]) -> Vec<f64> {
+    let mut total = 0.0;
+    let mut distances = Vec::with_capacity(points.len());
+    for (i, &point) in points.iter().enumerate() {
+        if i > 0 {
+            total += haversine_m(points[i - 1], point);
+        }
+        distances.push(total);
+    }
+    distances
+}
@@ -0,0 +1,159 @@
+//! Grade Module
+//!
+//! This module computes road grade (slope) from elevation data:
+//! - A smoothed grade percentage for each point, measured over a distance
+//!   window centred on the point rather than between neighbouring points
+//! - A histogram of how much of a ride's distance falls in each grade band
+//!
+//! Point-to-point grades are dominated by elevation noise when points are a
+//! few metres apart, so every grade here spans [`GRADE_WINDOW_M`], or the
+//! neighbouring points where they are further apart than that.
+
+use gpx::Gpx;                          // GPX parsing and representation
+use serde::{Deserialize, Serialize};  // Serialization framework
+
+use super::geo;
+
+/// Horizontal distance, in metres, over which each point's grade is measured.
+pub const GRADE_WINDOW_M: f64 = 50.0;
+
+/// Boundaries between histogram bands, in percent; the outer bands are open-ended.
+const HISTOGRAM_EDGES_PCT: [f64; 7] = [-8.0, -4.0, -1.0, 1.0, 4.0, 8.0, 12.0];
+
+/// Smoothed grade, in percent, at each point of a segment.
+///
+/// Each point's grade is the elevation change over the distance between the
+/// furthest elevated points within half a window behind and ahead of it.
+/// Where points are sparser than that (smart recording, planned routes), a
+/// side of the window reaches at least the nearest elevated point, so sparse
+/// files still get grades. Near the ends of a segment the window is
+/// truncated rather than shifted.
+///
+/// # Arguments
+/// * `distances` - Cumulative distance along the segment for each point, in metres
+/// * `elevations` - Elevation of each point, if known
+///
+/// # Returns
+/// * `Vec<Option<f64>>` - The grade at each point, `None` where the point has no
+///   elevation or no elevated neighbour at a different distance
+pub fn windowed_grades_pct(distances: &[f64], elevations: &[Option<f64>]) -> Vec<Option<f64>> {
+    let half_window = GRADE_WINDOW_M / 2.0;
+    let elevated: Vec<usize> = (0..elevations.len()).filter(|&i| elevations[i].is_some()).collect();
+
+    let mut grades = vec![None; elevations.len()];
+    let (mut behind, mut ahead) = (0, 0);
+    for (position, &i) in elevated.iter().enumerate() {
+        while distances[i] - distances[elevated[behind]] > half_window {
+            behind += 1;
+        }
+        ahead = ahead.max(position);
+        while ahead + 1 < elevated.len() && distances[elevated[ahead + 1]] - distances[i] <= half_window {
+            ahead += 1;
+        }
+
+        // Reach the nearest elevated neighbour on a side whose half window holds no other point
+        let start = elevated[behind.min(position.saturating_sub(1))];
+        let end = elevated[ahead.max((position + 1).min(elevated.len() - 1))];
+        let run = distances[end] - distances[start];
+        if run > 0.0 {
+            grades[i] = Some((elevations[end].unwrap_or(0.0) - elevations[start].unwrap_or(0.0)) / run * 100.0);
+        }
+    }
+
+    grades
+}
+
+/// Share of a ride's distance within one grade band.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GradeBucket {
+    pub min_grade_pct: Option<f64>,  // Lower bound, inclusive; `None` for the steepest descents
+    pub max_grade_pct: Option<f64>,  // Upper bound, exclusive; `None` for the steepest climbs
+    pub distance_m: f64,             // Distance ridden at a grade in this band
+    pub distance_ratio: f64,         // Share of graded distance in this band (0.0-1.0)
+}
+
+/// Distributes a GPX file's distance across grade bands.
+///
+/// Each stretch between consecutive points is counted in the band of the
+/// smoothed grade at its starting point; stretches starting at a point
+/// without a grade are left out, including from the ratio's denominator.
+///
+/// # Arguments
+/// * `gpx` - The parsed GPX structure
+///
+/// # Returns
+/// * `Vec<GradeBucket>` - One bucket per band, from steepest descent to steepest climb;
+///   empty if no point has a grade
+pub fn grade_histogram(gpx: &Gpx) -> Vec<GradeBucket> {
+    let mut band_distances = [0.0; HISTOGRAM_EDGES_PCT.len() + 1];
+
+    for segment in gpx.tracks.iter().flat_map(|track| &track.segments) {
+        let coordinates: Vec<(f64, f64)> = segment.points.iter()
+            .map(|point| (point.point().y(), point.point().x()))
+            .collect();
+        let distances = geo::cumulative_distances_m(&coordinates);
+        let elevations: Vec<Option<f64>> = segment.points.iter().map(|point| point.elevation).collect();
+        let grades = windowed_grades_pct(&distances, &elevations);
+
+        for i in 1..distances.len() {
+            if let Some(grade) = grades[i - 1] {
+                let band = HISTOGRAM_EDGES_PCT.iter().filter(|&&edge| grade >= edge).count();
+                band_distances[band] += distances[i] - distances[i - 1];
+            }
+        }
+    }
+
+    let total: f64 = band_distances.iter().sum();
+    if total <= 0.0 {
+        return Vec::new();
+    }
+
+    band_distances.iter()
+        .enumerate()
+        .map(|(band, &distance_m)| GradeBucket {
+            min_grade_pct: band.checked_sub(1).map(|edge| HISTOGRAM_EDGES_PCT[edge]),
+            max_grade_pct: HISTOGRAM_EDGES_PCT.get(band).copied(),
+            distance_m,
+            distance_ratio: distance_m / total,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grades_dense_points_over_the_window() {
+        let distances: Vec<f64> = (0..11).map(|i| i as f64 * 10.0).collect();
+        let elevations: Vec<Option<f64>> = (0..11).map(|i| Some(i as f64 * 0.5)).collect();
+
+        let grades = windowed_grades_pct(&distances, &elevations);
+        assert!(grades.iter().all(|grade| grade.is_some_and(|grade| (grade - 5.0).abs() < 1e-9)));
+    }
+
+    #[test]
+    fn grades_points_sparser_than_the_window() {
+        // Smart recording: 27-58 m between points, wider than the 25 m half window
+        let distances = [0.0, 27.0, 85.0, 130.0, 171.0];
+        let elevations: Vec<Option<f64>> = distances.iter().map(|distance| Some(distance * 0.04)).collect();
+
+        let grades = windowed_grades_pct(&distances, &elevations);
+        assert!(grades.iter().all(|grade| grade.is_some_and(|grade| (grade - 4.0).abs() < 1e-9)));
+    }
+
+    #[test]
+    fn skips_points_without_elevation() {
+        let distances = [0.0, 40.0, 80.0, 120.0];
+        let elevations = [Some(100.0), None, Some(102.0), Some(104.0)];
+
+        let grades = windowed_grades_pct(&distances, &elevations);
+        assert_eq!(grades[1], None);
+        assert!(grades[0].is_some_and(|grade| (grade - 2.5).abs() < 1e-9));
+    }
+
+    #[test]
+    fn single_elevated_point_has_no_grade() {
+        assert_eq!(windowed_grades_pct(&[0.0, 50.0], &[Some(10.0), None]), vec![None, None]);
+    }
+}
@@ -0,0 +1,164 @@
+//! GPX Input Decoding Module
+//!
+//! This module turns raw upload bytes into GPX text or FIT data:
+//! - Detects gzip-compressed input (`.gpx.gz`, `.fit.gz`) by its magic bytes
+//! - Decompresses it while enforcing the size cap, so zip bombs fail early
+//! - Tells FIT files from GPX text by the FIT header
+//! - Validates GPX text is UTF-8 and strips a leading byte order mark
+
+use std::io::Read;
+
+use flate2::bufread::GzDecoder; // Decompression functionality
+
+use super::fit;
+use crate::error::{GpxProcessorError, Result};
+
+/// Maximum accepted size of GPX text, compressed or not (50MB).
+pub const MAX_GPX_BYTES: usize = 50_000_000;
+
+/// Maximum number of points processed from a single file, GPX or FIT.
+pub const MAX_GPX_POINTS: usize = 100_000;
+
+/// The two magic bytes that open every gzip stream.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Ensures an input of `size` bytes is within [`MAX_GPX_BYTES`].
+pub fn check_size(size: usize) -> Result<()> {
+    if size > MAX_GPX_BYTES {
+        return Err(GpxProcessorError::TooLarge { size, max: MAX_GPX_BYTES });
+    }
+    Ok(())
+}
+
+/// Returns true if the bytes start with the gzip magic number.
+pub fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(&GZIP_MAGIC)
+}
+
+/// Decompresses a gzip stream, refusing to inflate it past [`MAX_GPX_BYTES`].
+///
+/// # Arguments
+/// * `data` - The gzip-compressed bytes
+///
+/// # Returns
+/// * `Result<Vec<u8>>` - The decompressed bytes or an error
+///
+/// # Errors
+/// * `Decompression` if the gzip stream is corrupt
+/// * `TooLarge` if the decompressed data exceeds 50MB
+pub fn gunzip_capped(data: &[u8]) -> Result<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    GzDecoder::new(data)
+        .take(MAX_GPX_BYTES as u64 + 1)
+        .read_to_end(&mut decompressed)
+        .map_err(|e| GpxProcessorError::Decompression(e.to_string()))?;
+
+    check_size(decompressed.len())?;
+    Ok(decompressed)
+}
+
+/// An uploaded file after decompression, sorted by format.
+#[derive(Debug)]
+pub enum Upload {
+    Gpx(String),   // GPX text, ready for parsing
+    Fit(Vec<u8>),  // FIT activity bytes, ready for `fit::decode_fit`
+}
+
+/// Decodes raw upload bytes into GPX text or FIT data, decompressing gzip input if needed.
+///
+/// # Arguments
+/// * `data` - A GPX or FIT file, plain or gzip-compressed
+///
+/// # Returns
+/// * `Result<Upload>` - The file contents by format, or an error
+///
+/// # Errors
+/// * `TooLarge` if the input or decompressed data exceeds 50MB
+/// * `Decompression` if the gzip stream is corrupt
+/// * `InvalidEncoding` if the file is neither FIT nor valid UTF-8 text
+pub fn decode_upload(data: &[u8]) -> Result<Upload> {
+    check_size(data.len())?;
+
+    let bytes = if is_gzip(data) {
+        gunzip_capped(data)?
+    } else {
+        data.to_vec()
+    };
+
+    if fit::is_fit(&bytes) {
+        return Ok(Upload::Fit(bytes));
+    }
+
+    let text = String::from_utf8(bytes)
+        .map_err(|e| GpxProcessorError::InvalidEncoding(e.to_string()))?;
+
+    // Editors on Windows like to prepend a BOM, which would fail the `<?xml` check
+    Ok(Upload::Gpx(match text.strip_prefix('\u{feff}') {
+        Some(stripped) => stripped.to_string(),
+        None => text,
+    }))
+}
+
+/// Decodes raw upload bytes into a GPX string, decompressing gzip input if needed.
+///
+/// Decompression reads at most one byte past [`MAX_GPX_BYTES`], so an
+/// oversized or malicious archive is rejected without inflating it fully.
+///
+/// # Arguments
+/// * `data` - Plain GPX bytes or a gzip-compressed GPX file
+///
+/// # Returns
+/// * `Result<String>` - The GPX text or an error
+///
+/// # Errors
+/// * `TooLarge` if the input or decompressed data exceeds 50MB
+/// * `Decompression` if the gzip stream is corrupt
+/// * `InvalidEncoding` if the text is not valid UTF-8
+/// * `NotGpx` if the file is a FIT file; use [`decode_upload`] to accept both
+pub fn decode_gpx_bytes(data: &[u8]) -> Result<String> {
+    match decode_upload(data)? {
+        Upload::Gpx(text) => Ok(text),
+        Upload::Fit(_) => Err(GpxProcessorError::NotGpx),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{write::GzEncoder, Compression};
+
+    use super::*;
+
+    /// Gzips `len` zero bytes without holding them in memory.
+    fn gzipped_zeros(len: usize) -> Vec<u8> {
+        let chunk = vec![0u8; 1 << 20];
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+        let mut remaining = len;
+        while remaining > 0 {
+            let take = remaining.min(chunk.len());
+            encoder.write_all(&chunk[..take]).unwrap();
+            remaining -= take;
+        }
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn gunzips_within_the_cap() {
+        assert_eq!(gunzip_capped(&gzipped_zeros(1000)).unwrap(), vec![0u8; 1000]);
+    }
+
+    #[test]
+    fn refuses_to_inflate_past_the_cap() {
+        let bomb = gzipped_zeros(MAX_GPX_BYTES + 1000);
+        assert!(bomb.len() < 1_000_000);
+        match gunzip_capped(&bomb) {
+            Err(GpxProcessorError::TooLarge { size, max }) => {
+                // Inflation stops one byte past the cap rather than running to the end
+                assert_eq!(size, MAX_GPX_BYTES + 1);
+                assert_eq!(max, MAX_GPX_BYTES);
+            }
+            other => panic!("expected TooLarge, got {:?}", other.map(|bytes| bytes.len())),
+        }
+    }
+}
@@ -0,0 +1,146 @@
+//! Ride Metadata Module
+//!
+//! Collects the descriptive fields of a GPX file into a compact block: name,
+//! description, activity type, start time and the application that created
+//! it. The block travels in the reduced payload, so printed maps can carry a
+//! title, and in the analysis, so the upload form can pre-fill one.
+//!
+//! Every text field is sanitized, because route planners and devices write
+//! these fields freely:
+//! - HTML tags (common in exported descriptions) and control characters are removed
+//! - Runs of whitespace collapse to one space; descriptions keep their line breaks
+//! - Values are cut to a maximum length, and empty values are dropped
+
+use gpx::Gpx;                         // GPX parsing and representation
+use serde::{Deserialize, Serialize};  // Serialization framework
+
+use super::reduce::unix_seconds;
+
+/// Longest name, activity type or creator kept, in characters.
+pub const MAX_NAME_CHARS: usize = 200;
+
+/// Longest description kept, in characters.
+pub const MAX_DESCRIPTION_CHARS: usize = 2_000;
+
+/// Descriptive fields of a GPX file, sanitized for display.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RideMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,           // Title of the ride or route
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub desc: Option<String>,           // Description, with line breaks kept
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub activity_type: Option<String>,  // Activity type as written by the device, e.g. "cycling"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time: Option<i64>,              // Start time in Unix seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub creator: Option<String>,        // Application or device that created the file
+}
+
+/// Collects the metadata of a GPX file.
+///
+/// Each field is taken from the file's `<metadata>` first and otherwise from
+/// its first track, then its first route. The start time is the first
+/// timestamp on a track or route point, falling back to the `<metadata>`
+/// time, which is often when the file was exported rather than ridden.
+///
+/// # Arguments
+/// * `gpx` - The parsed GPX structure
+///
+/// # Returns
+/// * `Option<RideMetadata>` - The metadata, or `None` if the file has none of these fields
+pub fn ride_metadata(gpx: &Gpx) -> Option<RideMetadata> {
+    let file = gpx.metadata.as_ref();
+    let track = gpx.tracks.first();
+    let route = gpx.routes.first();
+
+    let name = first_sanitized(
+        [file.and_then(|metadata| metadata.name.as_deref()), track.and_then(|track| track.name.as_deref()), route.and_then(|route| route.name.as_deref())],
+        false,
+        MAX_NAME_CHARS,
+    );
+    let desc = first_sanitized(
+        [file.and_then(|metadata| metadata.description.as_deref()), track.and_then(|track| track.description.as_deref()), route.and_then(|route| route.description.as_deref())],
+        true,
+        MAX_DESCRIPTION_CHARS,
+    );
+    let activity_type = first_sanitized(
+        [track.and_then(|track| track.type_.as_deref()), route.and_then(|route| route.type_.as_deref())],
+        false,
+        MAX_NAME_CHARS,
+    );
+
+    let time = gpx.tracks.iter()
+        .flat_map(|track| &track.segments)
+        .flat_map(|segment| &segment.points)
+        .chain(gpx.routes.iter().flat_map(|route| &route.points))
+        .find_map(|point| point.time)
+        .or_else(|| file.and_then(|metadata| metadata.time))
+        .map(unix_seconds);
+    let creator = first_sanitized([gpx.creator.as_deref()], false, MAX_NAME_CHARS);
+
+    let metadata = RideMetadata { name, desc, activity_type, time, creator };
+    (metadata != RideMetadata::default()).then_some(metadata)
+}
+
+/// Sanitizes each candidate in turn, returning the first with text left.
+fn first_sanitized<const N: usize>(candidates: [Option<&str>; N], keep_line_breaks: bool, max_chars: usize) -> Option<String> {
+    candidates.into_iter().flatten().find_map(|text| sanitize(text, keep_line_breaks, max_chars))
+}
+
+/// Cleans up a free-text field for display.
+///
+/// # Arguments
+/// * `text` - The text as written in the file
+/// * `keep_line_breaks` - Whether to keep line breaks (and turn `<br>` and `<p>` into them)
+/// * `max_chars` - Longest result kept, in characters
+///
+/// # Returns
+/// * `Option<String>` - The cleaned text, or `None` if nothing is left
+fn sanitize(text: &str, keep_line_breaks: bool, max_chars: usize) -> Option<String> {
+    let without_tags = strip_tags(text);
+
+    let lines = without_tags.lines()
+        .map(|line| line.split(|c: char| c.is_whitespace() || c.is_control()).filter(|word| !word.is_empty()).collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty());
+    let joined = lines.collect::<Vec<_>>().join(if keep_line_breaks { "\n" } else { " " });
+
+    let truncated: String = joined.chars().take(max_chars).collect();
+    let trimmed = truncated.trim_end();
+    (!trimmed.is_empty()).then(|| trimmed.to_string())
+}
+
+/// Removes HTML tags, turning line-breaking ones (`<br>`, `<p>`, `<div>`, `<li>`) into newlines.
+///
+/// A `<` only starts a tag when followed by a letter, `/` or `!`, so text
+/// such as "grade < 5%" is left alone.
+fn strip_tags(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('<') {
+        let after = &rest[start + 1..];
+        let starts_tag = after.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '/' || c == '!');
+        let end = after.find('>');
+        match end.filter(|_| starts_tag) {
+            Some(end) => {
+                output.push_str(&rest[..start]);
+                let tag_name: String = after[..end]
+                    .trim_start_matches('/')
+                    .chars()
+                    .take_while(char::is_ascii_alphanumeric)
+                    .collect::<String>()
+                    .to_ascii_lowercase();
+                output.push(if matches!(tag_name.as_str(), "br" | "p" | "div" | "li") { '\n' } else { ' ' });
+                rest = &after[end + 1..];
+            }
+            None => {
+                output.push_str(&rest[..=start]);
+                rest = after;
+            }
+        }
+    }
+
+    output.push_str(rest);
+    output
+}
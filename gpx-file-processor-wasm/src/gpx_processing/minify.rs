@@ -0,0 +1,463 @@
+//! Payload Minification Module
+//!
+//! Shrinks GPX XML and JSON text before compression without changing what it
+//! parses to. The format is detected from the first significant character.
+//!
+//! For XML the minifier:
+//! - Drops comments, processing instructions and whitespace between elements
+//! - Keeps text content verbatim, so newlines in `<desc>` survive
+//! - Declares each namespace once on the root element instead of on every element
+//!   that uses it, and drops declarations nothing uses
+//! - Drops attributes set to their default value, including `encoding="UTF-8"`
+//!   and `standalone="no"` in the XML declaration
+//! - Writes empty elements as `<name/>`
+//!
+//! For JSON it drops whitespace outside strings, and `//` and `/* */`
+//! comments left in hand-edited files.
+//!
+//! GPX documents rebuilt from stored payloads for download are minified, as
+//! the GPX writer indents every element. Stored payloads themselves are
+//! serialized by `payload::serialize_reduced`, whose output is already
+//! compact, so they are not minified.
+
+use std::collections::HashMap;
+
+use xml::{
+    common::{Position, XmlVersion},
+    escape::{escape_str_attribute, escape_str_pcdata},
+    name::OwnedName,
+    namespace::{NS_NO_PREFIX, NS_XML_PREFIX, NS_XML_URI},
+    reader::{ParserConfig, XmlEvent},
+};
+
+use crate::error::{GpxProcessorError, Result};
+
+/// Namespace of the XML Schema instance attributes (`xsi:nil`, `xsi:schemaLocation`).
+const XSI_URI: &str = "http://www.w3.org/2001/XMLSchema-instance";
+
+/// Attributes that can be dropped when set to their default: (namespace, local name, default).
+const DEFAULT_ATTRIBUTES: &[(&str, &str, &str)] = &[
+    (NS_XML_URI, "space", "default"),
+    (XSI_URI, "nil", "false"),
+];
+
+/// Minifies GPX XML or JSON text, detecting which it is.
+///
+/// A leading UTF-8 byte order mark and whitespace are ignored.
+///
+/// # Arguments
+/// * `text` - The XML or JSON text
+///
+/// # Returns
+/// * `Result<String>` - The minified text
+///
+/// # Errors
+/// * `ParseError` if XML text is malformed
+/// * `Serialization` if JSON text is malformed
+/// * `InvalidPayload` if the text is neither XML nor JSON
+pub fn minify(text: &str) -> Result<String> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text).trim_start();
+    match text.chars().next() {
+        Some('<') => minify_xml(text),
+        Some('{' | '[') => minify_json(text),
+        _ => Err(GpxProcessorError::InvalidPayload("text is neither XML nor JSON".to_string())),
+    }
+}
+
+/// Minifies XML text; see the module documentation for what is removed.
+///
+/// # Arguments
+/// * `xml` - The XML text
+///
+/// # Returns
+/// * `Result<String>` - The minified XML, always starting with an XML declaration
+///
+/// # Errors
+/// * `ParseError` if the XML is malformed
+pub fn minify_xml(xml: &str) -> Result<String> {
+    let events = significant_events(xml)?;
+    let root_bindings = root_bindings(&events);
+
+    let mut output = String::with_capacity(xml.len() / 2);
+    // In-scope namespace bindings, innermost last, and how many each open element added
+    let mut scope: Vec<(String, String)> = vec![(NS_NO_PREFIX.to_string(), String::new())];
+    let mut added_per_element: Vec<usize> = Vec::new();
+
+    let mut events = events.into_iter().peekable();
+    while let Some(event) = events.next() {
+        match event {
+            XmlEvent::StartDocument { version, standalone, .. } => {
+                // Output is always UTF-8, so only a non-default version or standalone="yes" is kept
+                output.push_str("<?xml version=\"");
+                output.push_str(match version {
+                    XmlVersion::Version10 => "1.0",
+                    XmlVersion::Version11 => "1.1",
+                });
+                output.push('"');
+                if standalone == Some(true) {
+                    output.push_str(" standalone=\"yes\"");
+                }
+                output.push_str("?>");
+            }
+            XmlEvent::StartElement { name, attributes, .. } => {
+                output.push('<');
+                push_name(&mut output, &name);
+
+                let mut required = if added_per_element.is_empty() { root_bindings.clone() } else { Vec::new() };
+                required.extend(element_bindings(&name, attributes.iter().map(|attribute| &attribute.name)));
+                let mut added = 0;
+                for (prefix, uri) in required {
+                    if lookup(&scope, &prefix) == Some(uri.as_str()) {
+                        continue;
+                    }
+                    output.push_str(" xmlns");
+                    if !prefix.is_empty() {
+                        output.push(':');
+                        output.push_str(&prefix);
+                    }
+                    output.push_str("=\"");
+                    output.push_str(&escape_attribute(&uri));
+                    output.push('"');
+                    scope.push((prefix, uri));
+                    added += 1;
+                }
+                added_per_element.push(added);
+
+                for attribute in attributes.iter().filter(|attribute| !is_default_attribute(&attribute.name, &attribute.value)) {
+                    output.push(' ');
+                    push_name(&mut output, &attribute.name);
+                    output.push_str("=\"");
+                    output.push_str(&escape_attribute(&attribute.value));
+                    output.push('"');
+                }
+
+                if matches!(events.peek(), Some(XmlEvent::EndElement { .. })) {
+                    events.next();
+                    output.push_str("/>");
+                    close_scope(&mut scope, &mut added_per_element);
+                } else {
+                    output.push('>');
+                }
+            }
+            XmlEvent::EndElement { name } => {
+                output.push_str("</");
+                push_name(&mut output, &name);
+                output.push('>');
+                close_scope(&mut scope, &mut added_per_element);
+            }
+            XmlEvent::Characters(text) | XmlEvent::Whitespace(text) => {
+                output.push_str(&escape_text(&text));
+            }
+            XmlEvent::CData(text) => {
+                output.push_str("<![CDATA[");
+                output.push_str(&text);
+                output.push_str("]]>");
+            }
+            XmlEvent::EndDocument | XmlEvent::ProcessingInstruction { .. } | XmlEvent::Comment(_) => {}
+        }
+    }
+
+    Ok(output)
+}
+
+/// Minifies JSON text by dropping whitespace and comments outside strings.
+///
+/// # Arguments
+/// * `json` - The JSON text
+///
+/// # Returns
+/// * `Result<String>` - The minified JSON, with keys and values in their original order
+///
+/// # Errors
+/// * `Serialization` if the result is not valid JSON
+pub fn minify_json(json: &str) -> Result<String> {
+    let mut output = String::with_capacity(json.len());
+    let mut chars = json.chars().peekable();
+    let mut in_string = false;
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            output.push(c);
+            match c {
+                '\\' => output.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match c {
+            '"' => {
+                in_string = true;
+                output.push(c);
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                // Line comment: skip to the end of the line
+                for next in chars.by_ref() {
+                    if next == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                // Block comment: skip past the closing `*/`
+                chars.next();
+                let mut previous = '\0';
+                for next in chars.by_ref() {
+                    if previous == '*' && next == '/' {
+                        break;
+                    }
+                    previous = next;
+                }
+            }
+            c if c.is_whitespace() => {}
+            c => output.push(c),
+        }
+    }
+
+    // Make sure only insignificant characters were removed
+    serde_json::from_str::<serde::de::IgnoredAny>(&output)?;
+    Ok(output)
+}
+
+/// Parses XML into the events the output is written from.
+///
+/// Whitespace-only text is kept only when it is an element's whole content,
+/// since there it may be the value (an empty-looking `<desc>`); everywhere
+/// else in a GPX document it is indentation.
+fn significant_events(xml: &str) -> Result<Vec<XmlEvent>> {
+    let config = ParserConfig::new()
+        .ignore_comments(true)
+        .coalesce_characters(true);
+    let mut reader = config.create_reader(xml.as_bytes());
+
+    let mut events = Vec::new();
+    let mut pending_whitespace = None;
+    loop {
+        let event = reader.next().map_err(|e| GpxProcessorError::ParseError {
+            line: Some(e.position().row + 1),
+            message: e.to_string(),
+        })?;
+
+        match event {
+            XmlEvent::Whitespace(text) => {
+                if matches!(events.last(), Some(XmlEvent::StartElement { .. })) {
+                    pending_whitespace = Some(text);
+                }
+                continue;
+            }
+            XmlEvent::EndElement { .. } => {
+                if let Some(text) = pending_whitespace.take() {
+                    events.push(XmlEvent::Whitespace(text));
+                }
+            }
+            XmlEvent::ProcessingInstruction { .. } | XmlEvent::Comment(_) => continue,
+            _ => pending_whitespace = None,
+        }
+
+        let done = matches!(event, XmlEvent::EndDocument);
+        events.push(event);
+        if done {
+            return Ok(events);
+        }
+    }
+}
+
+/// Namespace bindings to declare once on the root element.
+///
+/// A prefix qualifies when it is bound to the same namespace everywhere it
+/// is used; the rest are declared on the elements that need them.
+fn root_bindings(events: &[XmlEvent]) -> Vec<(String, String)> {
+    let mut uris: HashMap<String, Option<String>> = HashMap::new();
+    for event in events {
+        if let XmlEvent::StartElement { name, attributes, .. } = event {
+            for (prefix, uri) in element_bindings(name, attributes.iter().map(|attribute| &attribute.name)) {
+                uris.entry(prefix)
+                    .and_modify(|seen| if seen.as_deref() != Some(uri.as_str()) { *seen = None })
+                    .or_insert(Some(uri));
+            }
+        }
+    }
+
+    let mut bindings: Vec<(String, String)> = uris.into_iter()
+        .filter_map(|(prefix, uri)| uri.map(|uri| (prefix, uri)))
+        .filter(|(prefix, uri)| !(prefix.is_empty() && uri.is_empty()))
+        .collect();
+    // Stable output: the default namespace first, then prefixes alphabetically
+    bindings.sort();
+    bindings
+}
+
+/// Namespace bindings an element needs for its own name and its attributes.
+fn element_bindings<'a>(name: &'a OwnedName, attribute_names: impl Iterator<Item = &'a OwnedName>) -> Vec<(String, String)> {
+    let element = (
+        name.prefix.clone().unwrap_or_default(),
+        name.namespace.clone().unwrap_or_default(),
+    );
+    // Unprefixed attributes are in no namespace and need no declaration
+    let attributes = attribute_names.filter_map(|attribute| {
+        let prefix = attribute.prefix.as_deref()?;
+        (prefix != NS_XML_PREFIX).then(|| (prefix.to_string(), attribute.namespace.clone().unwrap_or_default()))
+    });
+
+    let mut bindings = vec![element];
+    for binding in attributes {
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+    bindings
+}
+
+/// Finds the namespace currently bound to a prefix.
+fn lookup<'a>(scope: &'a [(String, String)], prefix: &str) -> Option<&'a str> {
+    scope.iter().rev().find(|(bound, _)| bound == prefix).map(|(_, uri)| uri.as_str())
+}
+
+/// Removes the bindings declared by the element being closed.
+fn close_scope(scope: &mut Vec<(String, String)>, added_per_element: &mut Vec<usize>) {
+    let added = added_per_element.pop().unwrap_or(0);
+    scope.truncate(scope.len() - added);
+}
+
+/// Returns true if an attribute is set to its default value and can be dropped.
+fn is_default_attribute(name: &OwnedName, value: &str) -> bool {
+    DEFAULT_ATTRIBUTES.iter().any(|(namespace, local_name, default)| {
+        name.namespace.as_deref() == Some(*namespace) && name.local_name == *local_name && value == *default
+    })
+}
+
+/// Writes a qualified name as `prefix:local` or `local`.
+fn push_name(output: &mut String, name: &OwnedName) {
+    if let Some(prefix) = &name.prefix {
+        output.push_str(prefix);
+        output.push(':');
+    }
+    output.push_str(&name.local_name);
+}
+
+/// Escapes an attribute value, including tabs, which parsers otherwise normalize to spaces.
+fn escape_attribute(value: &str) -> String {
+    escape_str_attribute(value).replace('\t', "&#x9;")
+}
+
+/// Escapes text content, including carriage returns, which parsers otherwise normalize to newlines.
+fn escape_text(text: &str) -> String {
+    escape_str_pcdata(text).replace('\r', "&#xD;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_gpx_from_string;
+
+    /// A GPX file with the things minification has to get right: comments,
+    /// indentation, namespace declarations repeated on extension elements,
+    /// default-valued attributes and text with meaningful newlines.
+    const GPX: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>
+<!-- Exported by a device -->
+<gpx version="1.1" creator="Test Device"
+     xmlns="http://www.topografix.com/GPX/1/1"
+     xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance"
+     xmlns:unused="http://example.com/unused"
+     xsi:schemaLocation="http://www.topografix.com/GPX/1/1 http://www.topografix.com/GPX/1/1/gpx.xsd">
+  <metadata>
+    <name>Morning &amp; ride</name>
+    <desc>Line one
+  Line two &lt;indented&gt;</desc>
+  </metadata>
+  <trk xml:space="default">
+    <name><![CDATA[Loop <3>]]></name>
+    <desc>Trail  notes	with a tab</desc>
+    <trkseg>
+      <trkpt lat="42.7325" lon="-84.5555">
+        <ele>256.4</ele>
+        <time>2024-05-01T12:00:00Z</time>
+        <extensions>
+          <gpxtpx:TrackPointExtension xmlns:gpxtpx="http://www.garmin.com/xmlschemas/TrackPointExtension/v1">
+            <gpxtpx:hr>120</gpxtpx:hr>
+          </gpxtpx:TrackPointExtension>
+        </extensions>
+      </trkpt>
+      <!-- paused -->
+      <trkpt lat="42.7330" lon="-84.5560">
+        <ele>257.0</ele>
+        <time>2024-05-01T12:00:05Z</time>
+        <extensions>
+          <gpxtpx:TrackPointExtension xmlns:gpxtpx="http://www.garmin.com/xmlschemas/TrackPointExtension/v1">
+            <gpxtpx:hr>124</gpxtpx:hr>
+          </gpxtpx:TrackPointExtension>
+        </extensions>
+      </trkpt>
+    </trkseg>
+  </trk>
+</gpx>
+"#;
+
+    #[test]
+    fn minified_xml_parses_to_the_same_gpx() {
+        let minified = minify(GPX).unwrap();
+
+        assert_eq!(parse_gpx_from_string(&minified).unwrap(), parse_gpx_from_string(GPX).unwrap());
+        assert!(minified.len() < GPX.len());
+    }
+
+    #[test]
+    fn minified_xml_keeps_text_and_drops_noise() {
+        let minified = minify(GPX).unwrap();
+
+        assert!(minified.starts_with(r#"<?xml version="1.0"?><gpx"#));
+        assert!(minified.contains("<desc>Line one\n  Line two &lt;indented&gt;</desc>"));
+        assert!(minified.contains("<desc>Trail  notes\twith a tab</desc>"));
+        assert!(minified.contains("<name><![CDATA[Loop <3>]]></name>"));
+        assert!(!minified.contains("<!--"));
+        assert!(!minified.contains("xml:space"));
+        assert!(!minified.contains("unused"));
+        assert!(!minified.contains("\n  <"));
+        // The extension namespace is declared once, on the root element
+        assert_eq!(minified.matches("xmlns:gpxtpx=").count(), 1);
+        assert!(minified.contains(r#"<gpx xmlns="http://www.topografix.com/GPX/1/1" xmlns:gpxtpx="#));
+    }
+
+    #[test]
+    fn minified_xml_is_stable() {
+        let once = minify(GPX).unwrap();
+
+        assert_eq!(minify(&once).unwrap(), once);
+    }
+
+    #[test]
+    fn conflicting_prefixes_stay_on_their_elements() {
+        let xml = r#"<?xml version="1.0"?><gpx xmlns="http://www.topografix.com/GPX/1/1" version="1.1" creator="t"><extensions><a:x xmlns:a="urn:one"/><a:y xmlns:a="urn:two"/></extensions></gpx>"#;
+        let minified = minify(xml).unwrap();
+
+        assert_eq!(minified, xml);
+        assert_eq!(parse_gpx_from_string(&minified).unwrap(), parse_gpx_from_string(xml).unwrap());
+    }
+
+    #[test]
+    fn whitespace_only_content_is_kept() {
+        let xml = "<?xml version=\"1.0\"?>\n<gpx version=\"1.1\" creator=\"t\" xmlns=\"http://www.topografix.com/GPX/1/1\">\n  <metadata>\n    <desc>  </desc>\n  </metadata>\n</gpx>";
+        let minified = minify(xml).unwrap();
+
+        assert!(minified.contains("<desc>  </desc>"));
+        assert_eq!(parse_gpx_from_string(&minified).unwrap(), parse_gpx_from_string(xml).unwrap());
+    }
+
+    #[test]
+    fn json_whitespace_and_comments_are_removed_outside_strings() {
+        let json = "{\n  // reduced track\n  \"trk\": [ { \"trkseg\": [ /* one segment */ { \"trkpt\": [\n    { \"@lat\": 42.7, \"@lon\": -84.5, \"name\": \"a // b  /* c */\\\" d\" }\n  ] } ] } ]\n}\n";
+
+        assert_eq!(
+            minify(json).unwrap(),
+            r#"{"trk":[{"trkseg":[{"trkpt":[{"@lat":42.7,"@lon":-84.5,"name":"a // b  /* c */\" d"}]}]}]}"#
+        );
+    }
+
+    #[test]
+    fn malformed_input_is_rejected() {
+        assert!(matches!(minify("<gpx><trk></gpx>"), Err(GpxProcessorError::ParseError { .. })));
+        assert!(matches!(minify("{\"trk\": [}"), Err(GpxProcessorError::Serialization(_))));
+        assert!(matches!(minify("trk"), Err(GpxProcessorError::InvalidPayload(_))));
+    }
+}
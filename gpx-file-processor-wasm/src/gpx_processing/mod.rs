@@ -1,3 +1,17 @@
-
+pub mod binary;
+pub mod climbs;
 pub mod compress;
-pub mod reduce;
\ No newline at end of file
+pub mod fit;
+pub mod geo;
+pub mod grade;
+pub mod input;
+pub mod metadata;
+pub mod minify;
+pub mod payload;
+pub mod pipeline;
+pub mod polyline;
+pub mod profile;
+pub mod reduce;
+pub mod simplify;
+pub mod stats;
+pub mod verify;
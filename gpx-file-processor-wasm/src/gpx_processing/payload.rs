@@ -0,0 +1,362 @@
+//! Stored Payload Module
+//!
+//! This module owns the payload stored for each uploaded route. It builds the
+//! canonical payload every upload path writes, and decodes stored payloads
+//! back into reduced data or a standard GPX 1.1 document, so files downloaded
+//! from storage open in Garmin, Strava and other GPX tools.
+//!
+//! The canonical payload is the reduced GPX serialized in the selected
+//! [`PayloadFormat`] (bare JSON by default, never wrapped) and compressed
+//! (gzip by default). The
+//! decoder accepts every format the site has written:
+//! - Reduced JSON, the canonical payload (a bare `{"trk": ...}` object)
+//! - Reduced JSON wrapped in `<gpx>...</gpx>`, written by older uploads
+//! - The `SMLR` binary layout
+//! - Plain GPX XML
+//!
+//! Any of these may be compressed with one of the `CompressionCodec`s;
+//! compression is detected from the gzip magic bytes or the compression
+//! header rather than assumed.
+
+use geo_types::Point;  // Coordinates for rebuilt waypoints
+use gpx::{Gpx, GpxVersion, Metadata, Route, Track, TrackSegment, Waypoint};  // GPX representation
+
+use super::{binary, compress, input, minify, reduce::{PayloadFormat, ReduceOptions}};
+use crate::error::{GpxProcessorError, Result};
+use crate::{parse_gpx_from_string, write_gpx_from_parsed_gpx_string, SmlrGpx, SmlrTrackPoint};
+
+/// Creator recorded in GPX documents rebuilt from reduced payloads that kept none.
+const CREATOR: &str = "Michigan Bike Maps";
+
+/// Opening tag of the legacy wrapper around reduced JSON.
+const LEGACY_WRAPPER_OPEN: &str = "<gpx>";
+/// Closing tag of the legacy wrapper around reduced JSON.
+const LEGACY_WRAPPER_CLOSE: &str = "</gpx>";
+
+/// A built payload, ready for upload.
+#[derive(Debug, Clone)]
+pub struct Payload {
+    pub bytes: Vec<u8>,            // Compressed payload as stored
+    pub uncompressed_size: usize,  // Size of the serialized reduced GPX before compression
+}
+
+/// A reduced GPX decoded from a stored payload.
+#[derive(Debug)]
+pub struct DecodedPayload {
+    pub reduced: SmlrGpx,            // The reduced GPX
+    pub decompressed_size: usize,    // Size of the payload after removing compression
+}
+
+/// Serializes a reduced GPX in the payload format selected in `options`, before compression.
+///
+/// # Arguments
+/// * `reduced_gpx` - The reduced GPX
+/// * `options` - The options it was reduced with
+///
+/// # Returns
+/// * `Result<Vec<u8>>` - Bare JSON text or the binary layout, or a `Serialization` error
+///   (the binary layout also rejects non-finite or huge values)
+pub fn serialize_reduced(reduced_gpx: &SmlrGpx, options: &ReduceOptions) -> Result<Vec<u8>> {
+    Ok(match options.payload_format() {
+        PayloadFormat::Json => serde_json::to_vec(reduced_gpx)?,
+        PayloadFormat::Binary => binary::to_bytes(reduced_gpx, options)?,
+    })
+}
+
+/// Builds the canonical stored payload for a reduced GPX.
+///
+/// Every path that produces an upload goes through this function, so the
+/// sizes reported in the analysis always describe the bytes that are stored.
+///
+/// # Arguments
+/// * `reduced_gpx` - The reduced GPX
+/// * `options` - The options it was reduced with
+///
+/// # Returns
+/// * `Result<Payload>` - The compressed payload and its uncompressed size
+///
+/// # Errors
+/// * `Serialization` or `Compression` if a step fails
+pub fn build_payload(reduced_gpx: &SmlrGpx, options: &ReduceOptions) -> Result<Payload> {
+    let serialized = serialize_reduced(reduced_gpx, options)?;
+    Ok(Payload {
+        bytes: compress::compress_bytes(&serialized, &options.compression_options())?,
+        uncompressed_size: serialized.len(),
+    })
+}
+
+/// Format of a stored payload once any compression layer is removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoredFormat {
+    /// Bare reduced JSON, the canonical payload.
+    Json,
+    /// Reduced JSON inside a `<gpx>` wrapper, from older uploads.
+    WrappedJson,
+    /// The `SMLR` binary layout.
+    Binary,
+    /// A full GPX XML document.
+    GpxXml,
+}
+
+/// Identifies the format of decompressed payload bytes.
+///
+/// Leading whitespace and a UTF-8 byte order mark are ignored.
+///
+/// # Returns
+/// * `Option<StoredFormat>` - The detected format, or `None` if it is not one the site writes
+pub fn detect_format(bytes: &[u8]) -> Option<StoredFormat> {
+    if bytes.starts_with(&binary::MAGIC) {
+        return Some(StoredFormat::Binary);
+    }
+
+    let text = bytes.strip_prefix("\u{feff}".as_bytes()).unwrap_or(bytes).trim_ascii_start();
+    if text.starts_with(b"{") {
+        Some(StoredFormat::Json)
+    } else if text.strip_prefix(LEGACY_WRAPPER_OPEN.as_bytes()).is_some_and(|rest| rest.trim_ascii_start().starts_with(b"{")) {
+        Some(StoredFormat::WrappedJson)
+    } else if text.starts_with(b"<") {
+        Some(StoredFormat::GpxXml)
+    } else {
+        None
+    }
+}
+
+/// Converts a reduced GPX into a GPX 1.1 document.
+///
+/// Segment and route start times and point offsets, when kept, become
+/// absolute point timestamps; grades and sensor values have no
+/// equivalent the GPX writer supports and are dropped.
+/// Metadata becomes the document's `<metadata>` name, description and time,
+/// and the activity type is set on every track and route. The creator kept
+/// in the metadata is written back; without one the creator is this site.
+pub fn smlr_to_gpx(reduced: &SmlrGpx) -> Gpx {
+    let metadata = reduced.metadata.as_ref();
+    let activity_type = metadata.and_then(|metadata| metadata.activity_type.clone());
+
+    let waypoints = reduced.wpt.iter()
+        .map(|smlr_waypoint| {
+            let mut waypoint = Waypoint::new(Point::new(smlr_waypoint.lon, smlr_waypoint.lat));
+            waypoint.elevation = smlr_waypoint.ele;
+            waypoint.name = smlr_waypoint.name.clone();
+            waypoint.symbol = smlr_waypoint.sym.clone();
+            waypoint
+        })
+        .collect();
+
+    let routes = reduced.rte.iter()
+        .map(|smlr_route| {
+            let mut route = Route::new();
+            route.type_ = activity_type.clone();
+            route.points = to_waypoints(smlr_route.t0, &smlr_route.rtept);
+            route
+        })
+        .collect();
+
+    let tracks = reduced.trk.iter()
+        .map(|smlr_track| {
+            let mut track = Track::new();
+            track.type_ = activity_type.clone();
+            track.segments = smlr_track.trkseg.iter()
+                .map(|smlr_segment| {
+                    let mut segment = TrackSegment::new();
+                    segment.points = to_waypoints(smlr_segment.t0, &smlr_segment.trkpt);
+                    segment
+                })
+                .collect();
+            track
+        })
+        .collect();
+
+    Gpx {
+        version: GpxVersion::Gpx11,
+        creator: Some(metadata.and_then(|metadata| metadata.creator.clone()).unwrap_or_else(|| CREATOR.to_string())),
+        metadata: metadata.map(|metadata| Metadata {
+            name: metadata.name.clone(),
+            description: metadata.desc.clone(),
+            time: metadata.time.and_then(to_time),
+            ..Metadata::default()
+        }),
+        waypoints,
+        routes,
+        tracks,
+    }
+}
+
+/// Converts Unix seconds to a GPX timestamp, or `None` if out of range.
+pub fn to_time(seconds: i64) -> Option<gpx::Time> {
+    time::OffsetDateTime::from_unix_timestamp(seconds).ok().map(gpx::Time::from)
+}
+
+/// Converts reduced points back to GPX points, rebuilding timestamps from the start time.
+fn to_waypoints(t0: Option<i64>, points: &[SmlrTrackPoint]) -> Vec<Waypoint> {
+    points.iter()
+        .map(|smlr_point| {
+            let mut point = Waypoint::new(Point::new(smlr_point.lon, smlr_point.lat));
+            point.elevation = smlr_point.ele;
+            point.time = t0
+                .zip(smlr_point.dt)
+                .and_then(|(start, offset)| start.checked_add(offset))
+                .and_then(to_time);
+            point
+        })
+        .collect()
+}
+
+/// Decodes a stored payload in any supported format into a `Gpx` value.
+///
+/// # Arguments
+/// * `data` - The payload as stored, compressed or not
+///
+/// # Returns
+/// * `Result<Gpx>` - The route as a GPX 1.1 structure
+///
+/// # Errors
+/// * `Decompression` or `TooLarge` if the compression layer cannot be removed
+/// * `InvalidPayload` if the format is not recognised or the binary layout is malformed
+/// * `Serialization` if reduced JSON does not match the expected shape
+/// * `ParseError` if a GPX XML payload is malformed
+pub fn decode_payload(data: &[u8]) -> Result<Gpx> {
+    let bytes = decompress_stored(data)?;
+    match detect_format(&bytes) {
+        Some(StoredFormat::GpxXml) => {
+            // Re-emit GPX 1.0 uploads as 1.1 so every download has the same version
+            let mut gpx = parse_gpx_from_string(payload_text(&bytes)?)?;
+            gpx.version = GpxVersion::Gpx11;
+            Ok(gpx)
+        }
+        format => Ok(smlr_to_gpx(&parse_reduced(&bytes, format)?)),
+    }
+}
+
+/// Decodes a stored reduced payload back into the reduced GPX it was built from.
+///
+/// # Arguments
+/// * `data` - The payload as stored, compressed or not
+///
+/// # Returns
+/// * `Result<DecodedPayload>` - The reduced GPX and the payload's decompressed size
+///
+/// # Errors
+/// * `Decompression` or `TooLarge` if the compression layer cannot be removed
+/// * `InvalidPayload` if the payload is not reduced data or the binary layout is malformed
+/// * `Serialization` if reduced JSON does not match the expected shape
+pub fn decode_reduced(data: &[u8]) -> Result<DecodedPayload> {
+    let bytes = decompress_stored(data)?;
+    Ok(DecodedPayload {
+        reduced: parse_reduced(&bytes, detect_format(&bytes))?,
+        decompressed_size: bytes.len(),
+    })
+}
+
+/// Removes the compression layer from a stored payload, if it has one.
+fn decompress_stored(data: &[u8]) -> Result<Vec<u8>> {
+    input::check_size(data.len())?;
+    if compress::detect_codec(data).is_some() {
+        compress::decompress_bytes(data)
+    } else {
+        Ok(data.to_vec())
+    }
+}
+
+/// Parses decompressed payload bytes of a reduced format into a reduced GPX.
+fn parse_reduced(bytes: &[u8], format: Option<StoredFormat>) -> Result<SmlrGpx> {
+    match format {
+        Some(StoredFormat::Binary) => binary::from_bytes(bytes),
+        Some(StoredFormat::Json) => Ok(serde_json::from_str(payload_text(bytes)?)?),
+        Some(StoredFormat::WrappedJson) => {
+            let json = payload_text(bytes)?
+                .strip_prefix(LEGACY_WRAPPER_OPEN)
+                .and_then(|rest| rest.strip_suffix(LEGACY_WRAPPER_CLOSE))
+                .ok_or_else(|| GpxProcessorError::InvalidPayload("unterminated <gpx> wrapper".to_string()))?;
+            Ok(serde_json::from_str(json)?)
+        }
+        Some(StoredFormat::GpxXml) => Err(GpxProcessorError::InvalidPayload("payload is GPX XML, not reduced data".to_string())),
+        None => Err(GpxProcessorError::InvalidPayload("unrecognised payload format".to_string())),
+    }
+}
+
+/// Views decompressed text payload bytes as a string without a BOM or surrounding whitespace.
+fn payload_text(bytes: &[u8]) -> Result<&str> {
+    let text = std::str::from_utf8(bytes).map_err(|e| GpxProcessorError::InvalidEncoding(e.to_string()))?;
+    Ok(text.trim_start_matches('\u{feff}').trim())
+}
+
+/// Decodes a stored payload in any supported format into GPX 1.1 XML text.
+///
+/// The document is minified, as the GPX writer indents every element and
+/// downloads of long rides would otherwise be mostly whitespace.
+///
+/// # Arguments
+/// * `data` - The payload as stored, compressed or not
+///
+/// # Returns
+/// * `Result<String>` - A GPX 1.1 document
+///
+/// # Errors
+/// * Any error from [`decode_payload`], or `Serialization` if the XML cannot be written
+pub fn payload_to_gpx_xml(data: &[u8]) -> Result<String> {
+    minify::minify_xml(&write_gpx_from_parsed_gpx_string(decode_payload(data)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpx_processing::metadata::RideMetadata;
+
+    const REDUCED_JSON: &str = r#"{"trk":[{"trkseg":[{"trkpt":[{"@lat":42.9,"@lon":-85.6,"ele":200.5},{"@lat":42.91,"@lon":-85.61}]}]}]}"#;
+
+    #[test]
+    fn detects_each_stored_format() {
+        let mut binary = binary::MAGIC.to_vec();
+        binary.extend_from_slice(&[3, 0]);
+        assert_eq!(detect_format(&binary), Some(StoredFormat::Binary));
+
+        assert_eq!(detect_format(REDUCED_JSON.as_bytes()), Some(StoredFormat::Json));
+        assert_eq!(detect_format(format!("\u{feff}\n  {}", REDUCED_JSON).as_bytes()), Some(StoredFormat::Json));
+        assert_eq!(detect_format(format!("<gpx>\n{}</gpx>", REDUCED_JSON).as_bytes()), Some(StoredFormat::WrappedJson));
+        assert_eq!(detect_format(b"<?xml version=\"1.0\"?><gpx version=\"1.1\"></gpx>"), Some(StoredFormat::GpxXml));
+        assert_eq!(detect_format(b"<gpx version=\"1.1\"><trk/></gpx>"), Some(StoredFormat::GpxXml));
+        assert_eq!(detect_format(b"trk"), None);
+        assert_eq!(detect_format(b""), None);
+    }
+
+    #[test]
+    fn converts_a_legacy_wrapped_payload_to_gpx_xml() {
+        let wrapped = format!("<gpx>{}</gpx>", REDUCED_JSON);
+        let compressed = compress::compress_bytes(wrapped.as_bytes(), &compress::CompressionOptions::default()).unwrap();
+
+        let xml = crate::decompress_gpx_to_xml(&compressed).unwrap();
+        assert!(xml.contains("version=\"1.1\""));
+        assert!(xml.contains("creator=\"Michigan Bike Maps\""));
+        assert!(xml.contains("lat=\"42.9\""));
+        assert!(xml.contains("<ele>200.5</ele>"));
+
+        let gpx = decode_payload(&compressed).unwrap();
+        assert_eq!(gpx.tracks[0].segments[0].points.len(), 2);
+    }
+
+    #[test]
+    fn minifies_rebuilt_gpx_xml() {
+        let compressed = compress::compress_bytes(REDUCED_JSON.as_bytes(), &compress::CompressionOptions::default()).unwrap();
+        let xml = payload_to_gpx_xml(&compressed).unwrap();
+        assert!(!xml.contains(">\n"), "{}", xml);
+        assert_eq!(parse_gpx_from_string(&xml).unwrap(), decode_payload(&compressed).unwrap());
+    }
+
+    #[test]
+    fn writes_back_the_stored_creator() {
+        let mut reduced: SmlrGpx = serde_json::from_str(REDUCED_JSON).unwrap();
+        assert_eq!(smlr_to_gpx(&reduced).creator.as_deref(), Some(CREATOR));
+
+        reduced.metadata = Some(RideMetadata {
+            name: Some("Lakeshore loop".to_string()),
+            desc: None,
+            activity_type: None,
+            time: None,
+            creator: Some("Garmin Edge 540".to_string()),
+        });
+        let gpx = smlr_to_gpx(&reduced);
+        assert_eq!(gpx.creator.as_deref(), Some("Garmin Edge 540"));
+        assert_eq!(gpx.metadata.and_then(|metadata| metadata.name).as_deref(), Some("Lakeshore loop"));
+    }
+}
@@ -0,0 +1,252 @@
+//! GPX Processing Pipeline Module
+//!
+//! This module runs the whole upload flow on a single parse of the GPX file:
+//! 1. Parse the XML into a `Gpx` value once (or decode a FIT file into one)
+//! 2. Collect analysis metrics and ride statistics from that value (from the
+//!    routes instead, for a planned route with no tracks)
+//! 3. Reduce it to an `SmlrGpx`
+//! 4. Build the canonical compressed payload and verify it decodes to the original points
+//!
+//! Every public entry point in the crate that needs more than one of these
+//! steps goes through [`process_gpx`], [`process_fit`] or [`process_upload`],
+//! so an upload is never re-parsed.
+
+use std::{borrow::Cow, collections::HashMap};
+
+use gpx::{Gpx, Track, TrackSegment};  // GPX parsing and representation
+
+use super::{climbs, fit::{self, SensorSamples, SensorSummary}, grade, input::{self, Upload}, metadata, payload, profile, reduce::{self, ReduceOptions}, stats::{self, AnalysisOptions}, verify};
+use crate::error::Result;
+use crate::timing::now_ms;
+use crate::{
+    calculate_bounding_box, calculate_elevation_range, count_points, parse_gpx_from_string, GpxAnalysis,
+};
+
+/// Result of running the pipeline: the analysis plus the compressed payload it describes.
+#[derive(Debug)]
+pub struct ProcessedGpx {
+    pub analysis: GpxAnalysis,  // Metrics describing the original file and the payload
+    pub payload: Vec<u8>,       // Compressed reduced GPX, ready for upload
+}
+
+/// Parses, analyzes, reduces and compresses a GPX file in one pass.
+///
+/// # Arguments
+/// * `gpx_string` - The raw GPX file content as a string
+/// * `options` - Precision and simplification settings for the reduction step
+/// * `analysis_options` - Thresholds for the ride statistics
+///
+/// # Returns
+/// * `Result<ProcessedGpx>` - The analysis and compressed payload, or an error
+///
+/// # Errors
+/// * `TooLarge` or `TooManyPoints` if the file exceeds the processing limits
+/// * `ParseError`, `Serialization` or `Compression` if a processing step fails
+pub fn process_gpx(gpx_string: &str, options: &ReduceOptions, analysis_options: &AnalysisOptions) -> Result<ProcessedGpx> {
+    let start_time = now_ms();
+
+    // Check input size before spending time on parsing
+    input::check_size(gpx_string.len())?; // 50MB max
+
+    // Parse the original GPX file; this is the only parse of the upload
+    let original_gpx = parse_gpx_from_string(gpx_string)?;
+    let parsing_ms = now_ms() - start_time;
+
+    // Count points to ensure we don't process overly complex files
+    reduce::check_point_limit(&original_gpx)?;
+
+    let mut processed = process_parsed_gpx(&original_gpx, None, gpx_string.len(), options, analysis_options)?;
+    processed.analysis.timing_ms.insert("parsing".to_string(), parsing_ms);
+
+    Ok(processed)
+}
+
+/// Decodes, analyzes, reduces and compresses a FIT activity in one pass.
+///
+/// Each reduced point keeps its heart rate, cadence and power, and the
+/// analysis carries their ride-wide summary.
+///
+/// # Arguments
+/// * `data` - The FIT file bytes
+/// * `options` - Precision and simplification settings for the reduction step
+/// * `analysis_options` - Thresholds for the ride statistics
+///
+/// # Returns
+/// * `Result<ProcessedGpx>` - The analysis and compressed payload, or an error
+///
+/// # Errors
+/// * `TooLarge` or `TooManyPoints` if the file exceeds the processing limits
+/// * `ParseError` if the FIT file is malformed
+/// * `Serialization` or `Compression` if a processing step fails
+pub fn process_fit(data: &[u8], options: &ReduceOptions, analysis_options: &AnalysisOptions) -> Result<ProcessedGpx> {
+    let start_time = now_ms();
+
+    // Decoding checks the size and point-count limits itself
+    let activity = fit::decode_fit(data)?;
+    let parsing_ms = now_ms() - start_time;
+
+    let mut processed = process_parsed_gpx(&activity.gpx, Some(&activity.samples), data.len(), options, analysis_options)?;
+    processed.analysis.timing_ms.insert("parsing".to_string(), parsing_ms);
+
+    Ok(processed)
+}
+
+/// Processes raw upload bytes, GPX or FIT and plain or gzip-compressed.
+///
+/// # Arguments
+/// * `data` - The uploaded file bytes
+/// * `options` - Precision and simplification settings for the reduction step
+/// * `analysis_options` - Thresholds for the ride statistics
+///
+/// # Returns
+/// * `Result<ProcessedGpx>` - The analysis and compressed payload, or an error
+///
+/// # Errors
+/// * `TooLarge`, `Decompression` or `InvalidEncoding` if the upload cannot be decoded
+/// * Any error of [`process_gpx`] or [`process_fit`]
+pub fn process_upload(data: &[u8], options: &ReduceOptions, analysis_options: &AnalysisOptions) -> Result<ProcessedGpx> {
+    match input::decode_upload(data)? {
+        Upload::Gpx(gpx_string) => process_gpx(&gpx_string, options, analysis_options),
+        Upload::Fit(bytes) => process_fit(&bytes, options, analysis_options),
+    }
+}
+
+/// Runs the pipeline on an already-parsed GPX file.
+///
+/// Callers are responsible for the size and point-count limits, which
+/// [`process_gpx`] checks before handing over.
+///
+/// # Arguments
+/// * `original_gpx` - The parsed GPX structure
+/// * `samples` - Heart rate, cadence and power of each track point, for a FIT activity
+/// * `original_size` - Size in bytes of the GPX text or FIT file it was decoded from
+/// * `options` - Precision and simplification settings for the reduction step
+/// * `analysis_options` - Thresholds for the ride statistics
+///
+/// # Returns
+/// * `Result<ProcessedGpx>` - The analysis and compressed payload, or an error
+pub fn process_parsed_gpx(
+    original_gpx: &Gpx,
+    samples: Option<&SensorSamples>,
+    original_size: usize,
+    options: &ReduceOptions,
+    analysis_options: &AnalysisOptions,
+) -> Result<ProcessedGpx> {
+    let mut timings = HashMap::new();
+
+    // Extract basic metrics from the original GPX file
+    let original_point_count = count_points(original_gpx);
+    let tracks_count = original_gpx.tracks.len();
+    let segments_count = original_gpx.tracks.iter().map(|track| track.segments.len()).sum();
+    let metadata = metadata::ride_metadata(original_gpx);
+    let routes_count = original_gpx.routes.len();
+    let waypoints_count = original_gpx.waypoints.len();
+    let elevation_range = calculate_elevation_range(original_gpx);
+    let bounding_box = calculate_bounding_box(original_gpx);
+
+    // Ride statistics come from the tracks, or from the routes of a route-only file
+    let ridden = analyzed_tracks(original_gpx);
+    let ride_stats = stats::ride_stats(&ridden, analysis_options);
+    let climbs = climbs::detect_climbs(&ridden, analysis_options);
+    let grade_histogram = grade::grade_histogram(&ridden);
+    let elevation_profile = profile::elevation_profile(&ridden, analysis_options.profile_samples());
+    let sensors = samples.and_then(|samples| SensorSummary::from_samples(samples.iter().flatten().flatten()));
+
+    // Reduce the parsed GPX to its simplified structure
+    let reduce_start = now_ms();
+    let (reduced_gpx, reduce_report) = reduce::reduce_gpx(original_gpx, samples, options);
+    timings.insert("reduction".to_string(), now_ms() - reduce_start);
+
+    let reduced_point_count = reduced_gpx.point_count();
+
+    // Serialize and compress the reduced GPX into the payload that is uploaded
+    let compress_start = now_ms();
+    let built_payload = payload::build_payload(&reduced_gpx, options)?;
+    timings.insert("compression".to_string(), now_ms() - compress_start);
+
+    let reduced_size = built_payload.uncompressed_size;
+    let compressed_size = built_payload.bytes.len();
+    let compression_ratio = if original_size > 0 {
+        1.0 - (compressed_size as f64 / original_size as f64)
+    } else {
+        0.0
+    };
+    let point_reduction_ratio = if original_point_count > 0 {
+        1.0 - (reduced_point_count as f64 / original_point_count as f64)
+    } else {
+        0.0
+    };
+
+    // Decode the payload and check it matches the reduction and the original points
+    let verify_start = now_ms();
+    let check = verify::verify_payload(&built_payload.bytes, &reduced_gpx, original_gpx, &reduce_report, options);
+    timings.insert("verification".to_string(), now_ms() - verify_start);
+
+    // Create the analysis object with all collected metrics
+    let analysis = GpxAnalysis {
+        original_size_bytes: original_size,
+        reduced_size_bytes: reduced_size,
+        compressed_size_bytes: compressed_size,
+        compression_ratio,
+        point_count: original_point_count,
+        reduced_point_count,
+        point_reduction_ratio,
+        metadata,
+        tracks_count,
+        segments_count,
+        routes_count,
+        waypoints_count,
+        elevation_range,
+        elevation_gain_m: ride_stats.elevation.map(|change| change.gain_m),
+        elevation_loss_m: ride_stats.elevation.map(|change| change.loss_m),
+        elevation_gain_raw_m: ride_stats.elevation.map(|change| change.gain_raw_m),
+        elevation_loss_raw_m: ride_stats.elevation.map(|change| change.loss_raw_m),
+        climbs,
+        grade_histogram,
+        elevation_profile,
+        bounding_box,
+        total_distance_m: ride_stats.total_distance_m,
+        track_distances_m: ride_stats.track_distances_m,
+        elapsed_time_s: ride_stats.elapsed_time_s,
+        moving_time_s: ride_stats.moving_time_s,
+        average_moving_speed_mps: ride_stats.average_moving_speed_mps,
+        max_speed_mps: ride_stats.max_speed_mps,
+        simplification_error_m: reduce_report.max_deviation_m,
+        timing_ms: timings,
+        decompressed_size_bytes: check.decompressed_size,
+        decompressed_valid: check.valid,
+        decompressed_error: check.error,
+        decompressed_max_deviation_m: check.max_deviation_m,
+        sensors,
+    };
+
+    Ok(ProcessedGpx {
+        analysis,
+        payload: built_payload.bytes,
+    })
+}
+
+/// The GPX the ride statistics are computed from.
+///
+/// Planned routes exported from route planners often have only `<rte>`
+/// elements; each route is then treated as a single-segment track, so the
+/// file still gets a distance, climbs and an elevation profile. Files with
+/// any tracks are analyzed as they are.
+fn analyzed_tracks(gpx: &Gpx) -> Cow<'_, Gpx> {
+    if !gpx.tracks.is_empty() || gpx.routes.is_empty() {
+        return Cow::Borrowed(gpx);
+    }
+
+    let tracks = gpx.routes.iter()
+        .map(|route| {
+            let mut segment = TrackSegment::new();
+            segment.points = route.points.clone();
+            let mut track = Track::new();
+            track.name = route.name.clone();
+            track.segments = vec![segment];
+            track
+        })
+        .collect();
+
+    Cow::Owned(Gpx { tracks, ..Gpx::default() })
+}
@@ -0,0 +1,234 @@
+//! Encoded Polyline Module
+//!
+//! This module converts reduced track segments and routes to and from Google's encoded
+//! polyline algorithm format, which most map providers and routing tools accept
+//! and which is compact enough to embed in a URL:
+//! - Precision 5 (Google Maps) or 6 (OSRM, Valhalla) decimal places
+//! - An optional third dimension carrying elevation in centimetres, interleaved
+//!   after each latitude/longitude pair as GraphHopper does
+//!
+//! Each point is stored as deltas from the previous point. Plain 2D polylines
+//! decode in any standard decoder; 3D ones need a decoder that knows to expect
+//! the elevation value.
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;  // WebAssembly <-> JavaScript interop
+
+use serde::{Deserialize, Serialize};  // Serialization framework
+
+use crate::error::{GpxProcessorError, Result};
+use crate::{SmlrGpx, SmlrRoute, SmlrTrack, SmlrTrackPoint, SmlrTrackSegment};
+
+/// Fixed-point scale of the elevation dimension (centimetres).
+const ELEVATION_SCALE: f64 = 100.0;
+
+/// Polylines for every track segment and route of a reduced GPX.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EncodedPolylines {
+    pub tracks: Vec<Vec<String>>,  // One polyline per segment, grouped by track
+    pub routes: Vec<String>,       // One polyline per route
+}
+
+/// Number of decimal places coordinates are encoded with.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolylinePrecision {
+    /// Five decimal places, about 1.1 m; the Google Maps default.
+    E5,
+    /// Six decimal places, about 0.11 m; used by OSRM and Valhalla.
+    E6,
+}
+
+impl PolylinePrecision {
+    /// Multiplier from decimal degrees to encoded integers.
+    fn scale(self) -> f64 {
+        match self {
+            PolylinePrecision::E5 => 1e5,
+            PolylinePrecision::E6 => 1e6,
+        }
+    }
+}
+
+/// Appends one signed delta in the polyline character encoding.
+fn encode_value(delta: i64, output: &mut String) {
+    let mut value = if delta < 0 { !(delta << 1) } else { delta << 1 } as u64;
+    while value >= 0x20 {
+        output.push(char::from((0x20 | (value & 0x1f)) as u8 + 63));
+        value >>= 5;
+    }
+    output.push(char::from(value as u8 + 63));
+}
+
+/// Reads one signed delta from the polyline character encoding.
+fn decode_value(bytes: &mut std::slice::Iter<'_, u8>) -> Result<i64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(5) {
+        let byte = bytes.next()
+            .ok_or_else(|| GpxProcessorError::InvalidPayload("polyline ends mid-value".to_string()))?;
+        let chunk = byte.checked_sub(63)
+            .filter(|chunk| *chunk < 0x40)
+            .ok_or_else(|| GpxProcessorError::InvalidPayload(format!("invalid polyline character {:?}", char::from(*byte))))?;
+        value |= u64::from(chunk & 0x1f) << shift;
+        if chunk & 0x20 == 0 {
+            let value = value as i64;
+            return Ok(if value & 1 != 0 { !(value >> 1) } else { value >> 1 });
+        }
+    }
+    Err(GpxProcessorError::InvalidPayload("polyline value longer than 64 bits".to_string()))
+}
+
+/// Encodes the points of a reduced track segment or route as a polyline.
+///
+/// With `with_elevation`, points without an elevation repeat the previous
+/// point's elevation (zero at the start), since the format has no way to mark
+/// a value as missing.
+///
+/// # Arguments
+/// * `points` - The reduced points
+/// * `precision` - Decimal places for latitude and longitude
+/// * `with_elevation` - Whether to add elevation as a third dimension
+///
+/// # Returns
+/// * `String` - The encoded polyline
+fn encode_points(points: &[SmlrTrackPoint], precision: PolylinePrecision, with_elevation: bool) -> String {
+    let scale = precision.scale();
+    let mut output = String::new();
+    let (mut lat, mut lon, mut ele) = (0i64, 0i64, 0i64);
+
+    for point in points {
+        let (point_lat, point_lon) = ((point.lat * scale).round() as i64, (point.lon * scale).round() as i64);
+        encode_value(point_lat - lat, &mut output);
+        encode_value(point_lon - lon, &mut output);
+        (lat, lon) = (point_lat, point_lon);
+
+        if with_elevation {
+            let point_ele = point.ele.map_or(ele, |value| (value * ELEVATION_SCALE).round() as i64);
+            encode_value(point_ele - ele, &mut output);
+            ele = point_ele;
+        }
+    }
+
+    output
+}
+
+/// Decodes a polyline back into reduced points.
+///
+/// # Arguments
+/// * `encoded` - The encoded polyline
+/// * `precision` - Decimal places it was encoded with
+/// * `with_elevation` - Whether it carries elevation as a third dimension
+///
+/// # Returns
+/// * `Result<Vec<SmlrTrackPoint>>` - The decoded points, without grades, times or sensor values
+///
+/// # Errors
+/// * `InvalidPayload` if the string is not a well-formed polyline of the given dimension
+fn decode_points(encoded: &str, precision: PolylinePrecision, with_elevation: bool) -> Result<Vec<SmlrTrackPoint>> {
+    let scale = precision.scale();
+    let mut bytes = encoded.as_bytes().iter();
+    let (mut lat, mut lon, mut ele) = (0i64, 0i64, 0i64);
+    let mut trkpt = Vec::new();
+
+    while !bytes.as_slice().is_empty() {
+        lat = lat.wrapping_add(decode_value(&mut bytes)?);
+        lon = lon.wrapping_add(decode_value(&mut bytes)?);
+        let point_ele = if with_elevation {
+            ele = ele.wrapping_add(decode_value(&mut bytes)?);
+            Some(ele as f64 / ELEVATION_SCALE)
+        } else {
+            None
+        };
+
+        trkpt.push(SmlrTrackPoint {
+            lat: lat as f64 / scale,
+            lon: lon as f64 / scale,
+            ele: point_ele,
+            grade: None,
+            dt: None,
+            hr: None,
+            cad: None,
+            pwr: None,
+        });
+    }
+
+    Ok(trkpt)
+}
+
+/// Encodes every track segment and route of a reduced GPX as a polyline.
+///
+/// # Arguments
+/// * `gpx` - The reduced GPX
+/// * `precision` - Decimal places for latitude and longitude
+/// * `with_elevation` - Whether to add elevation in centimetres as a third dimension
+///
+/// # Returns
+/// * `EncodedPolylines` - One polyline per segment grouped by track, and one per route
+pub fn encode_polylines(gpx: &SmlrGpx, precision: PolylinePrecision, with_elevation: bool) -> EncodedPolylines {
+    EncodedPolylines {
+        tracks: gpx.trk.iter()
+            .map(|track| track.trkseg.iter()
+                .map(|segment| encode_points(&segment.trkpt, precision, with_elevation))
+                .collect())
+            .collect(),
+        routes: gpx.rte.iter()
+            .map(|route| encode_points(&route.rtept, precision, with_elevation))
+            .collect(),
+    }
+}
+
+/// Rebuilds a reduced GPX from polylines, as produced by [`encode_polylines`].
+///
+/// # Arguments
+/// * `polylines` - The track segment and route polylines
+/// * `precision` - Decimal places they were encoded with
+/// * `with_elevation` - Whether they carry elevation as a third dimension
+///
+/// # Returns
+/// * `Result<SmlrGpx>` - The decoded GPX, without grades, times or sensor values
+///
+/// # Errors
+/// * `InvalidPayload` if any string is not a well-formed polyline of the given dimension
+pub fn decode_polylines(polylines: &EncodedPolylines, precision: PolylinePrecision, with_elevation: bool) -> Result<SmlrGpx> {
+    let trk = polylines.tracks.iter()
+        .map(|track| {
+            let trkseg = track.iter()
+                .map(|encoded| Ok(SmlrTrackSegment { t0: None, trkpt: decode_points(encoded, precision, with_elevation)? }))
+                .collect::<Result<Vec<_>>>()?;
+            Ok(SmlrTrack { trkseg })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let rte = polylines.routes.iter()
+        .map(|encoded| Ok(SmlrRoute { t0: None, rtept: decode_points(encoded, precision, with_elevation)? }))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(SmlrGpx { metadata: None, trk, wpt: Vec::new(), rte })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(lat: f64, lon: f64, ele: f64) -> SmlrTrackPoint {
+        SmlrTrackPoint { lat, lon, ele: Some(ele), grade: None, dt: None, hr: None, cad: None, pwr: None }
+    }
+
+    #[test]
+    fn encodes_the_reference_polyline() {
+        let points = [point(38.5, -120.2, 0.0), point(40.7, -120.95, 0.0), point(43.252, -126.453, 0.0)];
+        assert_eq!(encode_points(&points, PolylinePrecision::E5, false), "_p~iF~ps|U_ulLnnqC_mqNvxq`@");
+    }
+
+    #[test]
+    fn round_trips_route_only_files() {
+        let gpx = SmlrGpx {
+            metadata: None,
+            trk: Vec::new(),
+            wpt: Vec::new(),
+            rte: vec![SmlrRoute { t0: None, rtept: vec![point(45.12345, -122.54321, 102.5), point(45.12401, -122.54298, 98.1)] }],
+        };
+
+        let polylines = encode_polylines(&gpx, PolylinePrecision::E5, true);
+        assert!(polylines.tracks.is_empty());
+        assert_eq!(polylines.routes.len(), 1);
+        assert_eq!(decode_polylines(&polylines, PolylinePrecision::E5, true).unwrap(), gpx);
+    }
+}
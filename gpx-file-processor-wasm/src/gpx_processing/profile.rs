@@ -0,0 +1,185 @@
+//! Elevation Profile Module
+//!
+//! This module resamples a GPX file's elevation data into a fixed number of
+//! evenly spaced samples along the ride, for drawing an elevation-vs-distance
+//! chart without sending every point to JavaScript:
+//! - Each sample covers an equal share of the total distance
+//! - Each sample carries the interpolated elevation at its centre plus the
+//!   minimum and maximum reached within it, so short spikes stay visible
+//!
+//! Tracks and segments are laid end to end; gaps between segments add no
+//! distance, matching the distance totals in the analysis.
+
+use gpx::Gpx;                          // GPX parsing and representation
+use serde::{Deserialize, Serialize};  // Serialization framework
+
+use super::geo;
+
+/// Largest number of samples a profile may be resampled to.
+pub const MAX_PROFILE_SAMPLES: usize = 10_000;
+
+/// One evenly spaced sample of an elevation profile.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileSample {
+    pub distance_m: f64,   // Distance from the start to the centre of the sample
+    pub elevation_m: f64,  // Interpolated elevation at the centre
+    pub min_m: f64,        // Lowest elevation within the sample
+    pub max_m: f64,        // Highest elevation within the sample
+}
+
+/// Elevation-vs-distance series resampled to a fixed number of samples.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ElevationProfile {
+    pub total_distance_m: f64,          // Length of the profile
+    pub sample_spacing_m: f64,          // Distance covered by each sample
+    pub samples: Vec<ProfileSample>,    // Samples in order of distance
+}
+
+/// Linearly interpolates the elevation at a distance along the profile.
+///
+/// `points` are `(distance, elevation)` pairs sorted by distance; distances
+/// outside the covered range take the nearest end's elevation.
+fn elevation_at(points: &[(f64, f64)], distance: f64) -> f64 {
+    let after = points.partition_point(|&(d, _)| d < distance);
+    if after == 0 {
+        return points[0].1;
+    }
+    if after == points.len() {
+        return points[after - 1].1;
+    }
+
+    let ((d0, e0), (d1, e1)) = (points[after - 1], points[after]);
+    if d1 > d0 {
+        e0 + (distance - d0) / (d1 - d0) * (e1 - e0)
+    } else {
+        e1
+    }
+}
+
+/// Resamples a GPX file's elevations into an elevation-vs-distance profile.
+///
+/// Points without an elevation add distance but no elevation data; the
+/// profile interpolates across them.
+///
+/// # Arguments
+/// * `gpx` - The parsed GPX structure
+/// * `samples` - Number of samples wanted, capped at [`MAX_PROFILE_SAMPLES`]
+///
+/// # Returns
+/// * `Option<ElevationProfile>` - The profile, or `None` if `samples` is zero,
+///   the file has no elevations or it covers no distance
+pub fn elevation_profile(gpx: &Gpx, samples: usize) -> Option<ElevationProfile> {
+    let samples = samples.min(MAX_PROFILE_SAMPLES);
+
+    // (distance, elevation) for every elevated point, with segments laid end to end
+    let mut points: Vec<(f64, f64)> = Vec::new();
+    let mut offset = 0.0;
+    for segment in gpx.tracks.iter().flat_map(|track| &track.segments) {
+        let coordinates: Vec<(f64, f64)> = segment.points.iter()
+            .map(|point| (point.point().y(), point.point().x()))
+            .collect();
+        let distances = geo::cumulative_distances_m(&coordinates);
+        points.extend(segment.points.iter()
+            .zip(&distances)
+            .filter_map(|(point, &distance)| point.elevation.map(|elevation| (offset + distance, elevation))));
+        offset += distances.last().copied().unwrap_or(0.0);
+    }
+
+    if samples == 0 || points.is_empty() || offset <= 0.0 {
+        return None;
+    }
+
+    let spacing = offset / samples as f64;
+    let mut next_point = 0;
+    let samples = (0..samples)
+        .map(|bucket| {
+            let (start, end) = (bucket as f64 * spacing, (bucket + 1) as f64 * spacing);
+
+            // The envelope includes the line's crossings of the bucket edges, so
+            // buckets between sparse points still span the line through them
+            let (edge_start, edge_end) = (elevation_at(&points, start), elevation_at(&points, end));
+            let mut min_m = edge_start.min(edge_end);
+            let mut max_m = edge_start.max(edge_end);
+            while next_point < points.len() && (points[next_point].0 < end || bucket + 1 == samples) {
+                min_m = min_m.min(points[next_point].1);
+                max_m = max_m.max(points[next_point].1);
+                next_point += 1;
+            }
+
+            let centre = start + spacing / 2.0;
+            ProfileSample { distance_m: centre, elevation_m: elevation_at(&points, centre), min_m, max_m }
+        })
+        .collect();
+
+    Some(ElevationProfile { total_distance_m: offset, sample_spacing_m: spacing, samples })
+}
+
+#[cfg(test)]
+mod tests {
+    use geo_types::Point;
+    use gpx::{Track, TrackSegment, Waypoint};
+
+    use super::*;
+
+    /// Degrees of latitude in 100 m along a meridian.
+    const STEP_DEG: f64 = 100.0 / 111_195.08;
+
+    /// A single-segment track with points 100 m apart at the given elevations.
+    fn track(elevations: &[Option<f64>]) -> Gpx {
+        let mut segment = TrackSegment::new();
+        segment.points = elevations.iter()
+            .enumerate()
+            .map(|(i, &elevation)| {
+                let mut point = Waypoint::new(Point::new(0.0, i as f64 * STEP_DEG));
+                point.elevation = elevation;
+                point
+            })
+            .collect();
+        let mut track = Track::new();
+        track.segments = vec![segment];
+        Gpx { tracks: vec![track], ..Gpx::default() }
+    }
+
+    #[test]
+    fn resamples_to_the_requested_count() {
+        let profile = elevation_profile(&track(&[Some(100.0); 11]), 4).unwrap();
+        assert!((profile.total_distance_m - 1000.0).abs() < 1e-3);
+        assert!((profile.sample_spacing_m - 250.0).abs() < 1e-3);
+        let centres: Vec<f64> = profile.samples.iter().map(|sample| sample.distance_m.round()).collect();
+        assert_eq!(centres, vec![125.0, 375.0, 625.0, 875.0]);
+    }
+
+    #[test]
+    fn caps_the_sample_count() {
+        let profile = elevation_profile(&track(&[Some(100.0); 11]), MAX_PROFILE_SAMPLES * 2).unwrap();
+        assert_eq!(profile.samples.len(), MAX_PROFILE_SAMPLES);
+    }
+
+    #[test]
+    fn envelope_keeps_spikes_between_sample_centres() {
+        let mut elevations = [Some(100.0); 11];
+        elevations[4] = Some(150.0);
+        let profile = elevation_profile(&track(&elevations), 4).unwrap();
+
+        let max: Vec<f64> = profile.samples.iter().map(|sample| sample.max_m).collect();
+        let min: Vec<f64> = profile.samples.iter().map(|sample| sample.min_m).collect();
+        assert_eq!(max, vec![100.0, 150.0, 100.0, 100.0]);
+        assert_eq!(min, vec![100.0; 4]);
+        // The centre of the spiked sample, 375 m, is three quarters up the climb to the spike
+        assert!((profile.samples[1].elevation_m - 137.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn interpolates_across_points_without_elevation() {
+        let profile = elevation_profile(&track(&[Some(100.0), None, Some(120.0)]), 2).unwrap();
+        assert!((profile.samples[0].elevation_m - 105.0).abs() < 1e-6);
+        assert!((profile.samples[1].elevation_m - 115.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn none_without_elevations_or_samples() {
+        assert!(elevation_profile(&track(&[None; 5]), 10).is_none());
+        assert!(elevation_profile(&track(&[Some(100.0); 5]), 0).is_none());
+        assert!(elevation_profile(&Gpx::default(), 10).is_none());
+    }
+}
@@ -2,22 +2,360 @@
 //!
 //! This module provides functionality for reducing the size of GPX files by:
 //! - Simplifying the GPX structure to only essential elements
-//! - Rounding coordinate precision to reduce decimal places
+//! - Rounding coordinate precision to a configurable number of decimal places
+//! - Optionally dropping redundant points with line simplification
+//! - Optionally interpolating missing elevations from neighbouring points
+//! - Optionally annotating each point with its smoothed grade
+//! - Optionally keeping timestamps as whole-second offsets from each segment's start
+//! - Keeping the heart rate, cadence and power of FIT track points
+//! - Keeping waypoints (position, name, symbol) and planned routes
+//! - Keeping a compact, sanitized metadata block (name, description, type, start time, creator)
 //! - Removing unnecessary metadata while preserving the route information
 
-use gpx::Gpx;                  // GPX parsing and representation
-use wasm_bindgen::JsValue;     // WebAssembly <-> JavaScript interop
+use gpx::{Gpx, Waypoint};      // GPX parsing and representation
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;  // WebAssembly <-> JavaScript interop
 
 // Import custom types from the parent module
-use crate::{ count_points, parse_gpx_from_string, SmlrGpx, SmlrTrack, SmlrTrackPoint, SmlrTrackSegment };
+use super::{compress::CompressionOptions, fit::{self, SensorSample, SensorSamples}, geo, grade, metadata, input::{self, Upload, MAX_GPX_POINTS}, simplify};
+use crate::error::{GpxProcessorError, Result};
+use crate::{ count_points, parse_gpx_from_string, SmlrGpx, SmlrRoute, SmlrTrack, SmlrTrackPoint, SmlrTrackSegment, SmlrWaypoint };
+
+/// Approximate length of one degree of latitude in metres.
+const METRES_PER_DEGREE: f64 = 111_320.0;
+
+/// Upper bound on coordinate decimal places; beyond this f64 noise dominates.
+const MAX_COORDINATE_DECIMALS: u32 = 10;
+
+/// Upper bound on elevation decimal places; no receiver resolves below a millimetre.
+const MAX_ELEVATION_DECIMALS: u32 = 3;
+
+/// How finely latitude and longitude are rounded during reduction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CoordinatePrecision {
+    /// Keep this many decimal places of a degree.
+    DecimalPlaces(u32),
+    /// Keep enough decimal places to resolve roughly this many metres on the ground.
+    Metres(f64),
+}
+
+impl CoordinatePrecision {
+    /// Resolves the precision to a number of decimal places.
+    ///
+    /// Metre precisions pick the fewest decimal places whose resolution is at
+    /// least as fine as requested (1 m -> 6 places, 10 m -> 5 places).
+    pub fn decimal_places(&self) -> u32 {
+        match *self {
+            CoordinatePrecision::DecimalPlaces(places) => places.min(MAX_COORDINATE_DECIMALS),
+            CoordinatePrecision::Metres(metres) => {
+                if !metres.is_finite() || metres <= 0.0 {
+                    return MAX_COORDINATE_DECIMALS;
+                }
+                let places = (METRES_PER_DEGREE / metres).log10().ceil();
+                places.clamp(0.0, MAX_COORDINATE_DECIMALS as f64) as u32
+            }
+        }
+    }
+}
+
+/// Line simplification applied to each track segment during reduction.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimplifyMode {
+    /// Keep every point; only precision is reduced.
+    None,
+    /// Ramer–Douglas–Peucker with a distance tolerance in metres.
+    DouglasPeucker,
+    /// Visvalingam–Whyatt with a maximum point budget per track.
+    VisvalingamWhyatt,
+}
+
+/// Encoding of the reduced GPX inside the compressed payload.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadFormat {
+    /// JSON text, readable after a plain gunzip.
+    Json,
+    /// The versioned `SMLR` binary layout; decode with `decode_smlr_binary`.
+    Binary,
+}
+
+/// Options controlling how a GPX file is reduced.
+///
+/// The defaults are tuned for trail maps: five coordinate decimal places
+/// (about 1.1 m), elevation to the nearest decimetre, no simplification and
+/// missing elevations left missing. Payloads default to JSON so existing
+/// stored files and readers keep working; the metadata block is kept, and
+/// readers that predate it ignore the extra key.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy)]
+pub struct ReduceOptions {
+    coordinate_precision: CoordinatePrecision,  // Rounding applied to lat/lon
+    elevation_decimals: u32,                    // Decimal places kept on elevation
+    simplify_mode: SimplifyMode,                // Point-dropping algorithm, if any
+    simplify_tolerance_m: f64,                  // Douglas–Peucker tolerance in metres
+    max_points_per_track: u32,                  // Visvalingam–Whyatt point budget
+    fill_elevation_gaps: bool,                  // Interpolate missing elevations
+    include_grade: bool,                        // Annotate points with smoothed grade
+    keep_times: bool,                           // Keep timestamps as offsets from segment start
+    payload_format: PayloadFormat,              // Encoding of the compressed payload
+    compression: CompressionOptions,            // Codec and level used on the payload
+    keep_metadata: bool,                        // Keep the name, description and other metadata
+}
+
+impl Default for ReduceOptions {
+    fn default() -> Self {
+        ReduceOptions {
+            coordinate_precision: CoordinatePrecision::DecimalPlaces(5),
+            elevation_decimals: 1,
+            simplify_mode: SimplifyMode::None,
+            simplify_tolerance_m: 2.0,
+            max_points_per_track: 5_000,
+            fill_elevation_gaps: false,
+            include_grade: false,
+            keep_times: false,
+            payload_format: PayloadFormat::Json,
+            compression: CompressionOptions::default(),
+            keep_metadata: true,
+        }
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl ReduceOptions {
+    /// Creates options with the trail-grade defaults.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new() -> ReduceOptions {
+        ReduceOptions::default()
+    }
+
+    /// Rounds coordinates to a fixed number of decimal places.
+    pub fn set_coordinate_decimals(&mut self, decimals: u32) {
+        self.coordinate_precision = CoordinatePrecision::DecimalPlaces(decimals);
+    }
+
+    /// Rounds coordinates to roughly the given ground distance in metres.
+    pub fn set_coordinate_precision_metres(&mut self, metres: f64) {
+        self.coordinate_precision = CoordinatePrecision::Metres(metres);
+    }
+
+    /// Rounds elevation to a fixed number of decimal places, at most three.
+    pub fn set_elevation_decimals(&mut self, decimals: u32) {
+        self.elevation_decimals = decimals.min(MAX_ELEVATION_DECIMALS);
+    }
+
+    /// Selects the line simplification algorithm.
+    pub fn set_simplify_mode(&mut self, mode: SimplifyMode) {
+        self.simplify_mode = mode;
+    }
+
+    /// Sets the Douglas–Peucker tolerance in metres.
+    pub fn set_simplify_tolerance_metres(&mut self, metres: f64) {
+        self.simplify_tolerance_m = metres;
+    }
+
+    /// Sets the Visvalingam–Whyatt point budget per track.
+    ///
+    /// Segment endpoints are always kept, so a budget below two points per
+    /// segment, including 0, leaves just the endpoints of each segment.
+    pub fn set_max_points_per_track(&mut self, max_points: u32) {
+        self.max_points_per_track = max_points;
+    }
+
+    /// Enables linear interpolation of missing elevations inside a segment.
+    pub fn set_fill_elevation_gaps(&mut self, fill: bool) {
+        self.fill_elevation_gaps = fill;
+    }
+
+    /// Adds a smoothed grade percentage to each reduced point, for colouring by steepness.
+    ///
+    /// Grades are measured on the full-resolution data before simplification,
+    /// so dropping points does not change them.
+    pub fn set_include_grade(&mut self, include: bool) {
+        self.include_grade = include;
+    }
+
+    /// Keeps point timestamps, so the reduced file can still give speeds or replay the ride.
+    ///
+    /// Each segment records the Unix time of its first timed point as `t0`,
+    /// and each timed point its offset from it in whole seconds as `dt`.
+    /// Sub-second precision is dropped.
+    pub fn set_keep_times(&mut self, keep: bool) {
+        self.keep_times = keep;
+    }
+
+    /// Selects how the reduced GPX is encoded in the compressed payload.
+    pub fn set_payload_format(&mut self, format: PayloadFormat) {
+        self.payload_format = format;
+    }
+
+    /// Keeps the file's name, description, activity type, start time and creator in the payload.
+    ///
+    /// On by default, so printed maps can carry the ride's title.
+    pub fn set_keep_metadata(&mut self, keep: bool) {
+        self.keep_metadata = keep;
+    }
+
+    /// Selects the codec and level used to compress the payload.
+    pub fn set_compression_options(&mut self, compression: &CompressionOptions) {
+        self.compression = *compression;
+    }
+
+    /// Number of decimal places coordinates will be rounded to.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn coordinate_decimals(&self) -> u32 {
+        self.coordinate_precision.decimal_places()
+    }
+
+    /// Number of decimal places elevation will be rounded to.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn elevation_decimals(&self) -> u32 {
+        self.elevation_decimals
+    }
+
+    /// The configured line simplification algorithm.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn simplify_mode(&self) -> SimplifyMode {
+        self.simplify_mode
+    }
+
+    /// The configured Douglas–Peucker tolerance in metres.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn simplify_tolerance_metres(&self) -> f64 {
+        self.simplify_tolerance_m
+    }
+
+    /// The configured Visvalingam–Whyatt point budget per track.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn max_points_per_track(&self) -> u32 {
+        self.max_points_per_track
+    }
+
+    /// Whether missing elevations are interpolated.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn fill_elevation_gaps(&self) -> bool {
+        self.fill_elevation_gaps
+    }
+
+    /// Whether reduced points carry a smoothed grade.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn include_grade(&self) -> bool {
+        self.include_grade
+    }
+
+    /// Whether reduced points keep their timestamps.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn keep_times(&self) -> bool {
+        self.keep_times
+    }
+
+    /// The configured payload encoding.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn payload_format(&self) -> PayloadFormat {
+        self.payload_format
+    }
+
+    /// Whether the payload keeps the file's metadata.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn keep_metadata(&self) -> bool {
+        self.keep_metadata
+    }
+
+    /// The configured payload compression.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn compression_options(&self) -> CompressionOptions {
+        self.compression
+    }
+}
+
+impl ReduceOptions {
+    /// Returns the coordinate precision as configured.
+    pub fn coordinate_precision(&self) -> CoordinatePrecision {
+        self.coordinate_precision
+    }
+}
+
+/// Side information produced while reducing a GPX file.
+#[derive(Debug, Clone, Default)]
+pub struct ReduceReport {
+    /// Largest distance in metres between a dropped point and the simplified line.
+    pub max_deviation_m: f64,
+    /// Indices of the original points kept in each segment, grouped by track.
+    pub kept_indices: Vec<Vec<Vec<usize>>>,
+    /// Indices of the original points kept in each route.
+    pub route_kept_indices: Vec<Vec<usize>>,
+}
+
+/// Picks the indices of the points in each segment of a track that survive simplification.
+fn simplified_indices(coords: &[Vec<(f64, f64)>], options: &ReduceOptions) -> Vec<Vec<usize>> {
+    match options.simplify_mode {
+        SimplifyMode::None => coords.iter().map(|seg| (0..seg.len()).collect()).collect(),
+        SimplifyMode::DouglasPeucker => coords.iter()
+            .map(|seg| simplify::douglas_peucker(seg, options.simplify_tolerance_m))
+            .collect(),
+        SimplifyMode::VisvalingamWhyatt => {
+            simplify::visvalingam_whyatt(coords, options.max_points_per_track as usize)
+        }
+    }
+}
+
+/// Fills interior elevation gaps in a segment by linear interpolation along distance.
+///
+/// A run of missing elevations is filled only when it has a known elevation on
+/// both sides; leading and trailing gaps are left as `None` rather than guessed.
+fn fill_elevation_gaps(cumulative: &[f64], elevations: &mut [Option<f64>]) {
+    let mut last_known: Option<usize> = None;
+    for i in 0..elevations.len() {
+        let Some(end_ele) = elevations[i] else {
+            continue;
+        };
+        if let Some(start) = last_known.filter(|&start| i > start + 1) {
+            let start_ele = elevations[start].unwrap_or(end_ele);
+            let span = cumulative[i] - cumulative[start];
+            for j in start + 1..i {
+                // Coincident points have no distance to interpolate over, so split evenly by index
+                let t = if span > 0.0 {
+                    (cumulative[j] - cumulative[start]) / span
+                } else {
+                    (j - start) as f64 / (i - start) as f64
+                };
+                elevations[j] = Some(start_ele + t * (end_ele - start_ele));
+            }
+        }
+        last_known = Some(i);
+    }
+}
+
+/// Whole seconds since the Unix epoch for a waypoint timestamp, rounded down.
+pub fn unix_seconds(time: gpx::Time) -> i64 {
+    time::OffsetDateTime::from(time).unix_timestamp()
+}
+
+/// Rounds a value to the given number of decimal places.
+fn round_to(value: f64, decimals: u32) -> f64 {
+    let factor = 10f64.powi(decimals as i32);
+    (value * factor).round() / factor
+}
+
+/// Reduces the size of a GPX file using the default [`ReduceOptions`].
+///
+/// # Arguments
+/// * `gpx_string` - The original GPX file content as an XML string
+///
+/// # Returns
+/// * `Result<String>` - A JSON string of the simplified GPX or an error
+pub fn reduce_gpx_size(gpx_string: &str) -> Result<String> {
+    reduce_gpx_size_with_options(gpx_string, &ReduceOptions::default())
+}
 
 /// Reduces the size of a GPX file by simplifying its structure and precision.
 ///
 /// This function performs several optimizations to reduce GPX file size:
 /// 1. Parses the original GPX XML string into a structured format
 /// 2. Converts it to a simplified structure that omits non-essential data
-/// 3. Reduces coordinate precision by rounding to two decimal places
-/// 4. Serializes the simplified structure to a JSON string
+/// 3. Drops redundant points per segment if a simplification mode is set
+/// 4. Reduces coordinate and elevation precision as set in `options`
+/// 5. Serializes the simplified structure to a JSON string
 ///
 /// The resulting JSON representation is typically much smaller than the original 
 /// XML while preserving the essential geospatial data needed for visualization 
@@ -25,89 +363,117 @@ use crate::{ count_points, parse_gpx_from_string, SmlrGpx, SmlrTrack, SmlrTrackP
 ///
 /// # Arguments
 /// * `gpx_string` - The original GPX file content as an XML string
+/// * `options` - Precision and simplification settings for the reduction
 ///
 /// # Returns
-/// * `Result<String, JsValue>` - A JSON string of the simplified GPX or an error
+/// * `Result<String>` - A JSON string of the simplified GPX or an error
 ///
 /// # Errors
-/// * Returns a JavaScript error value if GPX parsing fails
-/// * Returns a JavaScript error value if JSON serialization fails
-pub fn reduce_gpx_size(gpx_string: &str) -> Result<String, JsValue> {
+/// * `TooLarge` or `TooManyPoints` if the file exceeds the processing limits
+/// * `ParseError` if GPX parsing fails
+/// * `Serialization` if JSON serialization fails
+pub fn reduce_gpx_size_with_options(gpx_string: &str, options: &ReduceOptions) -> Result<String> {
       
     // Check input size
-    if gpx_string.len() > 50_000_000 { // 50MB max
-        return Err(JsValue::from_str("GPX file too large (max 50MB)"));
-    }
-    
-    // Limit the number of points to process
-    let max_points = 100000; 
+    input::check_size(gpx_string.len())?; // 50MB max
     
     // Parse the original GPX XML string into a structured Gpx object
-    let gpx: Gpx = parse_gpx_from_string(gpx_string).map_err(|e|
-        JsValue::from_str(&format!("Error reducing gpx file: {}", e))
-    )?;
-
-       // Count points to ensure we don't process overly complex files
-       let point_count = count_points(&gpx);
-       if point_count > max_points {
-           return Err(JsValue::from_str(
-               &format!("GPX file contains too many points ({} > {} max)", 
-                       point_count, max_points)
-           ));
-       }
-       
-
-    // Declare the simplified GPX structure to be populated
-    let smlr_gpx: SmlrGpx;
+    let gpx: Gpx = parse_gpx_from_string(gpx_string)?;
+
+    // Count points to ensure we don't process overly complex files
+    check_point_limit(&gpx)?;
+
+    let (smlr_gpx, _report) = reduce_gpx(&gpx, None, options);
+
+    // Serialize the simplified GPX to a JSON string
+    // This is more compact than XML and easier to process in web applications
+    let smlr_gpx_str = serde_json::to_string(&smlr_gpx)?;
+
+    // Return the simplified GPX as a JSON string
+    Ok(smlr_gpx_str)
+}
+
+/// Reduces raw upload bytes, GPX or FIT and plain or gzip-compressed, to reduced JSON.
+///
+/// # Arguments
+/// * `data` - The uploaded file bytes
+/// * `options` - Precision and simplification settings for the reduction
+///
+/// # Returns
+/// * `Result<String>` - A JSON string of the simplified GPX or an error
+///
+/// # Errors
+/// * `TooLarge`, `Decompression` or `InvalidEncoding` if the upload cannot be decoded
+/// * Any error of [`reduce_gpx_size_with_options`] or [`fit::decode_fit`]
+pub fn reduce_upload_with_options(data: &[u8], options: &ReduceOptions) -> Result<String> {
+    let activity = match input::decode_upload(data)? {
+        Upload::Gpx(gpx_string) => return reduce_gpx_size_with_options(&gpx_string, options),
+        Upload::Fit(bytes) => fit::decode_fit(&bytes)?,
+    };
+
+    let (smlr_gpx, _report) = reduce_gpx(&activity.gpx, Some(&activity.samples), options);
+    Ok(serde_json::to_string(&smlr_gpx)?)
+}
+
+
+/// Ensures a parsed GPX file is small enough to process.
+///
+/// # Arguments
+/// * `gpx` - The parsed GPX structure
+///
+/// # Errors
+/// * `TooManyPoints` if the file has more than [`MAX_GPX_POINTS`] track points, route points and waypoints
+pub fn check_point_limit(gpx: &Gpx) -> Result<()> {
+    let point_count = count_points(gpx);
+    if point_count > MAX_GPX_POINTS {
+        return Err(GpxProcessorError::TooManyPoints { count: point_count, max: MAX_GPX_POINTS });
+    }
+    Ok(())
+}
+
+/// Converts an already-parsed GPX file into its simplified form.
+///
+/// This is the shared core of [`reduce_gpx_size_with_options`] and the
+/// processing pipeline; it never parses or serializes, so callers holding a
+/// `Gpx` value can reduce it without another trip through the XML.
+///
+/// # Arguments
+/// * `gpx` - The parsed GPX structure
+/// * `samples` - Heart rate, cadence and power of each track point, for a FIT activity
+/// * `options` - Precision and simplification settings for the reduction
+///
+/// # Returns
+/// * `(SmlrGpx, ReduceReport)` - The simplified GPX and how far it deviates from the original
+pub fn reduce_gpx(gpx: &Gpx, samples: Option<&SensorSamples>, options: &ReduceOptions) -> (SmlrGpx, ReduceReport) {
+    let mut report = ReduceReport::default();
 
     // Create a vector to hold simplified tracks
     let mut smlr_trk: Vec<SmlrTrack> = Vec::new();
     
     // Process each track in the original GPX file
-    for (_track_index, track) in gpx.tracks.iter().enumerate() {
+    for (track_index, track) in gpx.tracks.iter().enumerate() {
         // Create a vector to hold simplified track segments for this track
         let mut smlr_trk_segs: Vec<SmlrTrackSegment> = Vec::new();
 
-        // Process each segment in the current track
-        for seg in &track.segments {
-            let smlr_seg: SmlrTrackSegment;
-
-            // Create a vector to hold simplified track points for this segment
-            let mut smlr_trk_pts: Vec<SmlrTrackPoint> = Vec::new();
-            
-            // Process each track point in the current segment
-            for track_point in &seg.points {
-                // Round latitude and longitude to 2 decimal places (approx. 1.1km precision)
-                // This significantly reduces file size while maintaining reasonable accuracy
-                let rounded_lat = (track_point.point().y() * 100.0).round() / 100.0;
-                let rounded_lon = (track_point.point().x() * 100.0).round() / 100.0;
-                
-                // Round elevation to 2 decimal places if available, or default to 0
-                // Note: Some GPX files may not include elevation data
-                let rounded_ele = match track_point.elevation {
-                    Some(ele) => (ele * 100.0).round() / 100.0,
-                    None => 0.0,
-                };
+        // Decide which points survive simplification across the whole track
+        let coords: Vec<Vec<(f64, f64)>> = track.segments.iter()
+            .map(|seg| coordinates(&seg.points))
+            .collect();
+        let kept_per_segment = simplified_indices(&coords, options);
+        let mut track_kept = Vec::with_capacity(kept_per_segment.len());
 
-                // Create a simplified track point with the rounded coordinates
-                let smlr_trk_pt = SmlrTrackPoint {
-                    lat: rounded_lat,
-                    lon: rounded_lon,
-                    ele: Some(rounded_ele),
-                };
-                
-                // Add the simplified point to our collection
-                smlr_trk_pts.push(smlr_trk_pt);
-            }
-
-            // Create a simplified track segment containing the processed points
-            smlr_seg = SmlrTrackSegment {
-                trkpt: smlr_trk_pts,
-            };
+        // Process each segment in the current track
+        for (segment_index, ((seg, seg_coords), kept)) in track.segments.iter().zip(&coords).zip(kept_per_segment).enumerate() {
+            let seg_samples = samples
+                .and_then(|samples| samples.get(track_index)?.get(segment_index))
+                .map_or(&[][..], Vec::as_slice);
+            let (t0, trkpt) = reduce_points(&seg.points, seg_coords, seg_samples, &kept, options, &mut report);
 
             // Add the simplified segment to our collection
-            smlr_trk_segs.push(smlr_seg);
+            smlr_trk_segs.push(SmlrTrackSegment { t0, trkpt });
+            track_kept.push(kept);
         }
+        report.kept_indices.push(track_kept);
 
         // Create a simplified track containing the processed segments
         let smlr_track = SmlrTrack {
@@ -118,19 +484,236 @@ pub fn reduce_gpx_size(gpx_string: &str) -> Result<String, JsValue> {
         smlr_trk.push(smlr_track);
     }
 
+    // Routes are simplified like a single-segment track
+    let mut smlr_rte: Vec<SmlrRoute> = Vec::new();
+    for route in &gpx.routes {
+        let route_coords = coordinates(&route.points);
+        let kept = simplified_indices(std::slice::from_ref(&route_coords), options).pop().unwrap_or_default();
+        let (t0, rtept) = reduce_points(&route.points, &route_coords, &[], &kept, options, &mut report);
+
+        smlr_rte.push(SmlrRoute { t0, rtept });
+        report.route_kept_indices.push(kept);
+    }
+
+    // Waypoints are kept as they are, with rounded positions
+    let smlr_wpt: Vec<SmlrWaypoint> = gpx.waypoints.iter()
+        .map(|waypoint| SmlrWaypoint {
+            lat: round_to(waypoint.point().y(), options.coordinate_decimals()),
+            lon: round_to(waypoint.point().x(), options.coordinate_decimals()),
+            ele: waypoint.elevation.map(|ele| round_to(ele, options.elevation_decimals)),
+            name: trimmed(waypoint.name.as_deref()),
+            sym: trimmed(waypoint.symbol.as_deref()),
+        })
+        .collect();
+
     // Create the final simplified GPX structure
-    smlr_gpx = SmlrGpx {
+    let smlr_gpx = SmlrGpx {
+        metadata: options.keep_metadata.then(|| metadata::ride_metadata(gpx)).flatten(),
         trk: smlr_trk,
+        wpt: smlr_wpt,
+        rte: smlr_rte,
     };
 
-    // Serialize the simplified GPX to a JSON string
-    // This is more compact than XML and easier to process in web applications
-    let smlr_gpx_str = serde_json::to_string(&smlr_gpx)
-        .map_err(|e| JsValue::from_str(&format!("Serialization error: {}", e)))?;
+    (smlr_gpx, report)
+}
+
+/// Latitude and longitude of each point, in that order.
+fn coordinates(points: &[Waypoint]) -> Vec<(f64, f64)> {
+    points.iter().map(|point| (point.point().y(), point.point().x())).collect()
+}
+
+/// Trims a text field, dropping it if nothing is left.
+fn trimmed(text: Option<&str>) -> Option<String> {
+    text.map(str::trim).filter(|text| !text.is_empty()).map(str::to_string)
+}
 
-    // Add <gpx> tag back to content - not needed all of the sudden 8/11/25
-    // smlr_gpx_str = format!("<gpx>{}</gpx>",smlr_gpx_str);
+/// Reduces the points of one track segment or route to the survivors of simplification.
+///
+/// # Arguments
+/// * `points` - Every point of the segment or route
+/// * `coords` - Their latitudes and longitudes
+/// * `samples` - Their heart rate, cadence and power, or empty if not recorded
+/// * `kept` - Indices of the points that survive simplification
+/// * `options` - Precision settings for the reduction
+/// * `report` - Report to record the simplification error in
+///
+/// # Returns
+/// * `(Option<i64>, Vec<SmlrTrackPoint>)` - The start time, if times are kept, and the reduced points
+fn reduce_points(
+    points: &[Waypoint],
+    coords: &[(f64, f64)],
+    samples: &[SensorSample],
+    kept: &[usize],
+    options: &ReduceOptions,
+    report: &mut ReduceReport,
+) -> (Option<i64>, Vec<SmlrTrackPoint>) {
+    let coordinate_decimals = options.coordinate_decimals();
+    let elevation_decimals = options.elevation_decimals;
+
+    // Track the worst deviation introduced by dropping points
+    report.max_deviation_m = report.max_deviation_m.max(simplify::max_deviation_m(coords, kept));
+
+    // Gather elevations from the full segment so gap filling sees every neighbour
+    let mut elevations: Vec<Option<f64>> = points.iter().map(|point| point.elevation).collect();
+    let distances = geo::cumulative_distances_m(coords);
+    if options.fill_elevation_gaps {
+        fill_elevation_gaps(&distances, &mut elevations);
+    }
+
+    // Grade comes from every point (after gap filling), not just the survivors
+    let grades = if options.include_grade {
+        grade::windowed_grades_pct(&distances, &elevations)
+    } else {
+        Vec::new()
+    };
+
+    // Whole-second Unix times of the points, if kept; the first one kept is the segment's epoch
+    let times: Vec<Option<i64>> = if options.keep_times {
+        points.iter().map(|point| point.time.map(unix_seconds)).collect()
+    } else {
+        Vec::new()
+    };
+    let t0 = kept.iter().find_map(|&index| times.get(index).copied().flatten());
+
+    // Create a vector to hold simplified track points for this segment
+    let mut smlr_trk_pts: Vec<SmlrTrackPoint> = Vec::with_capacity(kept.len());
     
-    // Return the simplified GPX as a JSON string
-    Ok(smlr_gpx_str)
-}
\ No newline at end of file
+    // Process each track point that survives simplification
+    for &index in kept {
+        let track_point = &points[index];
+
+        // Round latitude and longitude to the configured precision
+        let rounded_lat = round_to(track_point.point().y(), coordinate_decimals);
+        let rounded_lon = round_to(track_point.point().x(), coordinate_decimals);
+        
+        // Round elevation if available; missing elevation stays missing
+        // Note: Some GPX files may not include elevation data
+        let rounded_ele = elevations[index].map(|ele| round_to(ele, elevation_decimals));
+        let rounded_grade = grades.get(index).copied().flatten().map(|grade| round_to(grade, 1));
+        let time_offset = times.get(index).copied().flatten().zip(t0).map(|(time, start)| time - start);
+        let sample = samples.get(index).copied().unwrap_or_default();
+
+        // Create a simplified track point with the rounded coordinates
+        let smlr_trk_pt = SmlrTrackPoint {
+            lat: rounded_lat,
+            lon: rounded_lon,
+            ele: rounded_ele,
+            grade: rounded_grade,
+            dt: time_offset,
+            hr: sample.heart_rate_bpm,
+            cad: sample.cadence_rpm,
+            pwr: sample.power_w,
+        };
+        
+        // Add the simplified point to our collection
+        smlr_trk_pts.push(smlr_trk_pt);
+    }
+
+    (t0, smlr_trk_pts)
+}
+
+#[cfg(test)]
+mod tests {
+    use geo_types::Point;
+    use gpx::{Track, TrackSegment};
+
+    use super::*;
+    use crate::gpx_processing::payload::to_time;
+
+    /// A single-segment track along a meridian with the given Unix times.
+    fn timed_track(times: &[Option<i64>]) -> Gpx {
+        let mut segment = TrackSegment::new();
+        segment.points = times.iter()
+            .enumerate()
+            .map(|(i, &time)| {
+                let mut point = Waypoint::new(Point::new(-85.6, 42.9 + i as f64 * 0.001));
+                point.time = time.and_then(to_time);
+                point
+            })
+            .collect();
+        let mut track = Track::new();
+        track.segments = vec![segment];
+        Gpx { tracks: vec![track], ..Gpx::default() }
+    }
+
+    #[test]
+    fn maps_metre_precisions_to_decimal_places() {
+        let mut options = ReduceOptions::new();
+        for (metres, decimals) in [(1.0, 6), (10.0, 5), (1000.0, 3), (0.0, MAX_COORDINATE_DECIMALS)] {
+            options.set_coordinate_precision_metres(metres);
+            assert_eq!(options.coordinate_decimals(), decimals, "{} m", metres);
+        }
+    }
+
+    #[test]
+    fn caps_coordinate_decimals() {
+        let mut options = ReduceOptions::new();
+        options.set_coordinate_decimals(12);
+        assert_eq!(options.coordinate_decimals(), MAX_COORDINATE_DECIMALS);
+        options.set_coordinate_decimals(4);
+        assert_eq!(options.coordinate_decimals(), 4);
+    }
+
+    #[test]
+    fn keeps_times_as_offsets_from_the_first_timed_point() {
+        let gpx = timed_track(&[None, Some(1_700_000_000), Some(1_700_000_005), None, Some(1_700_000_042)]);
+
+        let mut options = ReduceOptions::new();
+        options.set_keep_times(true);
+        let (reduced, _) = reduce_gpx(&gpx, None, &options);
+        let segment = &reduced.trk[0].trkseg[0];
+        assert_eq!(segment.t0, Some(1_700_000_000));
+        let offsets: Vec<Option<i64>> = segment.trkpt.iter().map(|point| point.dt).collect();
+        assert_eq!(offsets, vec![None, Some(0), Some(5), None, Some(42)]);
+
+        let (reduced, _) = reduce_gpx(&gpx, None, &ReduceOptions::new());
+        let segment = &reduced.trk[0].trkseg[0];
+        assert_eq!(segment.t0, None);
+        assert!(segment.trkpt.iter().all(|point| point.dt.is_none()));
+    }
+
+    #[test]
+    fn fills_interior_gaps_along_distance() {
+        // The gap's points are unevenly spaced, so the fill follows distance rather than index
+        let cumulative = [0.0, 100.0, 400.0, 500.0, 600.0];
+        let mut elevations = [Some(100.0), None, None, Some(150.0), Some(160.0)];
+        fill_elevation_gaps(&cumulative, &mut elevations);
+        assert_eq!(elevations, [Some(100.0), Some(110.0), Some(140.0), Some(150.0), Some(160.0)]);
+    }
+
+    #[test]
+    fn splits_gaps_between_coincident_points_by_index() {
+        let cumulative = [0.0, 0.0, 0.0, 0.0];
+        let mut elevations = [Some(100.0), None, None, Some(130.0)];
+        fill_elevation_gaps(&cumulative, &mut elevations);
+        assert_eq!(elevations, [Some(100.0), Some(110.0), Some(120.0), Some(130.0)]);
+    }
+
+    #[test]
+    fn leaves_leading_and_trailing_gaps_missing() {
+        let cumulative = [0.0, 100.0, 200.0, 300.0, 400.0, 500.0];
+        let mut elevations = [None, Some(100.0), None, Some(120.0), None, None];
+        fill_elevation_gaps(&cumulative, &mut elevations);
+        assert_eq!(elevations, [None, Some(100.0), Some(110.0), Some(120.0), None, None]);
+
+        let mut elevations = [None, None, None];
+        fill_elevation_gaps(&cumulative[..3], &mut elevations);
+        assert_eq!(elevations, [None, None, None]);
+    }
+
+    #[test]
+    fn fills_gaps_only_when_asked() {
+        let mut gpx = timed_track(&[None; 3]);
+        let points = &mut gpx.tracks[0].segments[0].points;
+        points[0].elevation = Some(200.0);
+        points[2].elevation = Some(210.0);
+
+        let (reduced, _) = reduce_gpx(&gpx, None, &ReduceOptions::new());
+        assert_eq!(reduced.trk[0].trkseg[0].trkpt[1].ele, None);
+
+        let mut options = ReduceOptions::new();
+        options.set_fill_elevation_gaps(true);
+        let (reduced, _) = reduce_gpx(&gpx, None, &options);
+        assert_eq!(reduced.trk[0].trkseg[0].trkpt[1].ele, Some(205.0));
+    }
+}
@@ -0,0 +1,383 @@
+//! Track Simplification Module
+//!
+//! This module provides line simplification algorithms used to drop
+//! redundant track points before the reduced GPX is serialized:
+//! - Ramer–Douglas–Peucker, driven by a distance tolerance in metres
+//! - Visvalingam–Whyatt, driven by a point budget per track
+//!
+//! All algorithms take `(lat, lon)` pairs in decimal degrees and return the
+//! indices of the points to keep, in order. The first and last points of each
+//! segment are always kept so segments still start and end where they did.
+
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+/// Approximate length of one degree of latitude in metres.
+const METRES_PER_DEGREE: f64 = 111_320.0;
+
+/// Equirectangular projection centred on a reference latitude.
+///
+/// Accurate to well under a metre over the few kilometres a track segment
+/// typically spans, and far cheaper than geodesic math per point.
+struct LocalProjection {
+    origin_lat: f64,
+    origin_lon: f64,
+    lon_scale: f64,
+}
+
+impl LocalProjection {
+    fn new(origin: (f64, f64)) -> Self {
+        LocalProjection {
+            origin_lat: origin.0,
+            origin_lon: origin.1,
+            lon_scale: origin.0.to_radians().cos() * METRES_PER_DEGREE,
+        }
+    }
+
+    /// Projects a `(lat, lon)` pair to planar `(x, y)` metres.
+    fn project(&self, point: (f64, f64)) -> (f64, f64) {
+        (
+            (point.1 - self.origin_lon) * self.lon_scale,
+            (point.0 - self.origin_lat) * METRES_PER_DEGREE,
+        )
+    }
+}
+
+/// Distance in metres from `p` to the line segment `a`-`b`, all in planar coordinates.
+fn distance_to_segment(p: (f64, f64), a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let length_sq = dx * dx + dy * dy;
+
+    // Degenerate segment: fall back to point distance
+    if length_sq == 0.0 {
+        return (p.0 - a.0).hypot(p.1 - a.1);
+    }
+
+    // Clamp the projection onto the segment so points beyond the ends measure to the endpoint
+    let t = (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / length_sq).clamp(0.0, 1.0);
+    (p.0 - (a.0 + t * dx)).hypot(p.1 - (a.1 + t * dy))
+}
+
+/// Simplifies a polyline with the Ramer–Douglas–Peucker algorithm.
+///
+/// Points closer than `tolerance_m` metres to the simplified line are dropped.
+/// The implementation uses an explicit stack so 100k-point segments cannot
+/// overflow the (small) WebAssembly call stack.
+///
+/// # Arguments
+/// * `points` - `(lat, lon)` pairs in decimal degrees
+/// * `tolerance_m` - Maximum allowed deviation from the original line, in metres
+///
+/// # Returns
+/// * `Vec<usize>` - Sorted indices of the points to keep
+pub fn douglas_peucker(points: &[(f64, f64)], tolerance_m: f64) -> Vec<usize> {
+    if points.len() < 3 || tolerance_m.is_nan() || tolerance_m <= 0.0 {
+        return (0..points.len()).collect();
+    }
+
+    let projection = LocalProjection::new(points[0]);
+    let planar: Vec<(f64, f64)> = points.iter().map(|&p| projection.project(p)).collect();
+
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+
+    // Each stack entry is a span whose endpoints are already kept
+    let mut stack = vec![(0, points.len() - 1)];
+    while let Some((start, end)) = stack.pop() {
+        if end <= start + 1 {
+            continue;
+        }
+
+        // Find the point farthest from the chord between the span endpoints
+        let mut max_distance = 0.0;
+        let mut max_index = start;
+        for (index, &point) in planar.iter().enumerate().take(end).skip(start + 1) {
+            let distance = distance_to_segment(point, planar[start], planar[end]);
+            if distance > max_distance {
+                max_distance = distance;
+                max_index = index;
+            }
+        }
+
+        // Keep it and split the span if it deviates beyond the tolerance
+        if max_distance > tolerance_m {
+            keep[max_index] = true;
+            stack.push((start, max_index));
+            stack.push((max_index, end));
+        }
+    }
+
+    keep.iter()
+        .enumerate()
+        .filter_map(|(index, &kept)| kept.then_some(index))
+        .collect()
+}
+
+/// Largest distance in metres between a dropped point and the simplified line.
+///
+/// # Arguments
+/// * `points` - The original `(lat, lon)` pairs of one segment
+/// * `kept` - Sorted indices of the points that survived simplification
+///
+/// # Returns
+/// * `f64` - The maximum deviation, or 0.0 when nothing was dropped
+pub fn max_deviation_m(points: &[(f64, f64)], kept: &[usize]) -> f64 {
+    let Some(&first) = points.first() else {
+        return 0.0;
+    };
+    let projection = &LocalProjection::new(first);
+
+    kept.windows(2)
+        .flat_map(|pair| {
+            let a = projection.project(points[pair[0]]);
+            let b = projection.project(points[pair[1]]);
+            points[pair[0] + 1..pair[1]]
+                .iter()
+                .map(move |&p| distance_to_segment(projection.project(p), a, b))
+        })
+        .fold(0.0, f64::max)
+}
+
+/// Candidate for removal in the Visvalingam–Whyatt heap, ordered smallest area first.
+struct Candidate {
+    area: f64,
+    index: usize,
+    version: u32,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed so the std max-heap pops the smallest area (then lowest index) first
+        other.area.total_cmp(&self.area).then_with(|| other.index.cmp(&self.index))
+    }
+}
+
+/// Area in square metres of the triangle formed by three planar points.
+fn triangle_area(a: (f64, f64), b: (f64, f64), c: (f64, f64)) -> f64 {
+    ((b.0 - a.0) * (c.1 - a.1) - (c.0 - a.0) * (b.1 - a.1)).abs() / 2.0
+}
+
+/// Simplifies all segments of a track with the Visvalingam–Whyatt algorithm.
+///
+/// Points are removed least-significant first, where significance is the
+/// area of the triangle a point forms with its current neighbours, until the
+/// whole track fits in `max_points`. The budget is shared across segments so
+/// detailed segments keep more points than straight ones. Segment endpoints
+/// are never removed, so the budget cannot go below two points per segment:
+/// a budget smaller than that, including 0, keeps only each segment's
+/// endpoints (the single point of a one-point segment).
+///
+/// # Arguments
+/// * `segments` - `(lat, lon)` pairs for each segment of one track
+/// * `max_points` - Maximum number of points to keep across the track
+///
+/// # Returns
+/// * `Vec<Vec<usize>>` - Sorted indices of the points to keep, per segment
+pub fn visvalingam_whyatt(segments: &[Vec<(f64, f64)>], max_points: usize) -> Vec<Vec<usize>> {
+    let total: usize = segments.iter().map(Vec::len).sum();
+    let Some(&origin) = segments.iter().flatten().next() else {
+        return segments.iter().map(|_| Vec::new()).collect();
+    };
+    if total <= max_points {
+        return segments.iter().map(|seg| (0..seg.len()).collect()).collect();
+    }
+
+    // Flatten the track into one point list with per-segment neighbour links
+    let projection = LocalProjection::new(origin);
+    let mut planar = Vec::with_capacity(total);
+    let mut segment_of = Vec::with_capacity(total);
+    let mut prev: Vec<Option<usize>> = Vec::with_capacity(total);
+    let mut next: Vec<Option<usize>> = Vec::with_capacity(total);
+    for (segment_index, seg) in segments.iter().enumerate() {
+        let offset = planar.len();
+        for (i, &point) in seg.iter().enumerate() {
+            planar.push(projection.project(point));
+            segment_of.push(segment_index);
+            prev.push((i > 0).then(|| offset + i - 1));
+            next.push((i + 1 < seg.len()).then(|| offset + i + 1));
+        }
+    }
+
+    let mut removed = vec![false; total];
+    let mut versions = vec![0u32; total];
+    let mut heap = BinaryHeap::new();
+    for index in 0..total {
+        if let (Some(p), Some(n)) = (prev[index], next[index]) {
+            let area = triangle_area(planar[p], planar[index], planar[n]);
+            heap.push(Candidate { area, index, version: 0 });
+        }
+    }
+
+    let mut remaining = total;
+    let mut last_area = 0.0_f64;
+    while remaining > max_points {
+        let Some(candidate) = heap.pop() else {
+            break; // Only segment endpoints are left
+        };
+        if removed[candidate.index] || candidate.version != versions[candidate.index] {
+            continue; // Stale heap entry
+        }
+
+        // Effective areas never decrease, so a removal can't make a neighbour cheaper to drop
+        last_area = last_area.max(candidate.area);
+        removed[candidate.index] = true;
+        remaining -= 1;
+
+        let (p, n) = match (prev[candidate.index], next[candidate.index]) {
+            (Some(p), Some(n)) => (p, n),
+            _ => continue,
+        };
+        next[p] = Some(n);
+        prev[n] = Some(p);
+
+        // Re-score the neighbours now that their triangles have changed
+        for neighbour in [p, n] {
+            if let (Some(np), Some(nn)) = (prev[neighbour], next[neighbour]) {
+                versions[neighbour] += 1;
+                let area = triangle_area(planar[np], planar[neighbour], planar[nn]).max(last_area);
+                heap.push(Candidate { area, index: neighbour, version: versions[neighbour] });
+            }
+        }
+    }
+
+    let mut kept: Vec<Vec<usize>> = segments.iter().map(|_| Vec::new()).collect();
+    let mut offset = 0;
+    for (index, &segment_index) in segment_of.iter().enumerate() {
+        if index > 0 && segment_of[index - 1] != segment_index {
+            offset = index;
+        }
+        if !removed[index] {
+            kept[segment_index].push(index - offset);
+        }
+    }
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use geo_types::Point;
+    use gpx::{Gpx, Track, TrackSegment, Waypoint};
+
+    use super::*;
+    use crate::gpx_processing::reduce::{self, ReduceOptions, SimplifyMode};
+
+    /// Points about 7.9 m apart along the 45th parallel.
+    fn line(count: usize) -> Vec<(f64, f64)> {
+        (0..count).map(|i| (45.0, i as f64 * 0.0001)).collect()
+    }
+
+    /// Points along the same parallel with every other one pushed 30 m north.
+    fn zigzag(count: usize) -> Vec<(f64, f64)> {
+        (0..count).map(|i| (45.0 + (i % 2) as f64 * 30.0 / METRES_PER_DEGREE, i as f64 * 0.0001)).collect()
+    }
+
+    /// A straight line with one point pushed about 50 m north.
+    fn spike() -> Vec<(f64, f64)> {
+        let mut points = line(10);
+        points[5].0 += 50.0 / METRES_PER_DEGREE;
+        points
+    }
+
+    #[test]
+    fn collapses_a_straight_line_to_its_endpoints() {
+        assert_eq!(douglas_peucker(&line(10), 1.0), vec![0, 9]);
+    }
+
+    #[test]
+    fn keeps_a_spike_above_the_tolerance() {
+        // Points 4 and 6 sit about 25 m from the chords to the spike
+        assert_eq!(douglas_peucker(&spike(), 30.0), vec![0, 5, 9]);
+        assert_eq!(douglas_peucker(&spike(), 10.0), vec![0, 4, 5, 6, 9]);
+        assert_eq!(douglas_peucker(&spike(), 60.0), vec![0, 9]);
+    }
+
+    #[test]
+    fn handles_zero_and_infinite_tolerances() {
+        let all: Vec<usize> = (0..10).collect();
+        assert_eq!(douglas_peucker(&spike(), 0.0), all);
+        assert_eq!(douglas_peucker(&spike(), f64::NAN), all);
+        assert_eq!(douglas_peucker(&spike(), f64::INFINITY), vec![0, 9]);
+    }
+
+    #[test]
+    fn keeps_short_segments_whole() {
+        for count in 0..3 {
+            let all: Vec<usize> = (0..count).collect();
+            assert_eq!(douglas_peucker(&line(count), 10.0), all);
+            assert_eq!(max_deviation_m(&line(count), &all), 0.0);
+        }
+    }
+
+    #[test]
+    fn measures_the_deviation_of_dropped_points() {
+        assert_eq!(max_deviation_m(&spike(), &(0..10).collect::<Vec<_>>()), 0.0);
+        assert!((max_deviation_m(&spike(), &[0, 9]) - 50.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn reports_the_same_deviation_as_the_reduction() {
+        let mut segment = TrackSegment::new();
+        segment.points = spike().iter().map(|&(lat, lon)| Waypoint::new(Point::new(lon, lat))).collect();
+        let mut track = Track::new();
+        track.segments = vec![segment];
+        let gpx = Gpx { tracks: vec![track], ..Gpx::default() };
+
+        let mut options = ReduceOptions::default();
+        options.set_simplify_mode(SimplifyMode::DouglasPeucker);
+        options.set_simplify_tolerance_metres(60.0);
+        let (_reduced, report) = reduce::reduce_gpx(&gpx, None, &options);
+
+        assert_eq!(report.kept_indices, vec![vec![vec![0, 9]]]);
+        assert_eq!(report.max_deviation_m, max_deviation_m(&spike(), &[0, 9]));
+    }
+
+    #[test]
+    fn shares_the_point_budget_across_segments() {
+        let segments = vec![line(20), zigzag(20)];
+        let kept = visvalingam_whyatt(&segments, 10);
+
+        // The straight segment gives up every interior point before the zigzag loses any
+        assert_eq!(kept[0], vec![0, 19]);
+        assert_eq!(kept[1].len(), 8);
+        assert_eq!((kept[1].first(), kept[1].last()), (Some(&0), Some(&19)));
+        assert!(kept[1].windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn maps_removed_points_back_to_their_segment() {
+        // The only zero-area point is the second one of the straight, second segment
+        let segments = vec![zigzag(7), line(5)];
+        let kept = visvalingam_whyatt(&segments, 11);
+        assert_eq!(kept, vec![(0..7).collect::<Vec<_>>(), vec![0, 2, 3, 4]]);
+    }
+
+    #[test]
+    fn keeps_every_point_within_the_budget() {
+        let segments = vec![zigzag(7), line(5)];
+        assert_eq!(visvalingam_whyatt(&segments, 12), vec![(0..7).collect::<Vec<_>>(), (0..5).collect()]);
+    }
+
+    #[test]
+    fn keeps_only_endpoints_below_two_points_per_segment() {
+        let segments = vec![line(5), zigzag(7), line(1), Vec::new()];
+        let endpoints = vec![vec![0, 4], vec![0, 6], vec![0], Vec::new()];
+
+        // A budget of 0, and one below the number of segments
+        assert_eq!(visvalingam_whyatt(&segments, 0), endpoints);
+        assert_eq!(visvalingam_whyatt(&segments, 2), endpoints);
+    }
+}
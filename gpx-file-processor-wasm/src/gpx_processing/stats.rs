@@ -0,0 +1,386 @@
+//! Ride Statistics Module
+//!
+//! This module computes the headline ride numbers reported in `GpxAnalysis`:
+//! - Total distance, per track and overall
+//! - Elapsed and moving time from waypoint timestamps
+//! - Average moving speed and maximum speed
+//! - Elevation gain and loss, raw and hysteresis-filtered
+//!
+//! Distances are haversine sums between consecutive points of a segment; gaps
+//! between segments (e.g. a pause with the recorder off) add no distance or
+//! climbing.
+
+use gpx::{Gpx, TrackSegment};  // GPX parsing and representation
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;  // WebAssembly <-> JavaScript interop
+
+use super::geo;
+
+/// Shortest time window, in seconds, over which maximum speed is measured.
+///
+/// Measuring over a few seconds instead of point-to-point keeps a single
+/// jittery GPS fix from registering as a sprint.
+const MAX_SPEED_WINDOW_S: f64 = 5.0;
+
+/// Options controlling how ride statistics are computed.
+///
+/// The default moving threshold of 0.5 m/s (1.8 km/h) is below walking pace,
+/// so hike-a-bike sections count as moving while stops and GPS drift do not.
+/// The default elevation hysteresis of 3 m ignores typical barometer and
+/// GPS jitter without hiding short punchy climbs. Climbs are reported from
+/// 15 m of gain at an average of 3% or steeper, which catches the short
+/// climbs typical of Michigan terrain. No elevation profile is produced
+/// unless a sample count is set.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy)]
+pub struct AnalysisOptions {
+    moving_speed_threshold_mps: f64,  // Slowest speed that counts as moving
+    elevation_hysteresis_m: f64,      // Smallest elevation change counted toward gain/loss
+    min_climb_gain_m: f64,            // Smallest gain for a climb to be reported
+    min_climb_grade_pct: f64,         // Shallowest average grade for a climb to be reported
+    profile_samples: usize,           // Samples in the elevation profile; zero for none
+}
+
+impl Default for AnalysisOptions {
+    fn default() -> Self {
+        AnalysisOptions {
+            moving_speed_threshold_mps: 0.5,
+            elevation_hysteresis_m: 3.0,
+            min_climb_gain_m: 15.0,
+            min_climb_grade_pct: 3.0,
+            profile_samples: 0,
+        }
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl AnalysisOptions {
+    /// Creates options with the default thresholds.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new() -> AnalysisOptions {
+        AnalysisOptions::default()
+    }
+
+    /// Sets the slowest speed, in metres per second, that counts as moving.
+    pub fn set_moving_speed_threshold_mps(&mut self, mps: f64) {
+        self.moving_speed_threshold_mps = mps;
+    }
+
+    /// The slowest speed, in metres per second, that counts as moving.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn moving_speed_threshold_mps(&self) -> f64 {
+        self.moving_speed_threshold_mps
+    }
+
+    /// Sets the smallest elevation change, in metres, counted toward smoothed gain and loss.
+    ///
+    /// Zero makes the smoothed figures equal the raw ones.
+    pub fn set_elevation_hysteresis_m(&mut self, metres: f64) {
+        self.elevation_hysteresis_m = metres.max(0.0);
+    }
+
+    /// The smallest elevation change, in metres, counted toward smoothed gain and loss.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn elevation_hysteresis_m(&self) -> f64 {
+        self.elevation_hysteresis_m
+    }
+
+    /// Sets the smallest elevation gain, in metres, for a climb to be reported.
+    pub fn set_min_climb_gain_m(&mut self, metres: f64) {
+        self.min_climb_gain_m = metres;
+    }
+
+    /// The smallest elevation gain, in metres, for a climb to be reported.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn min_climb_gain_m(&self) -> f64 {
+        self.min_climb_gain_m
+    }
+
+    /// Sets the shallowest average grade, in percent, for a climb to be reported.
+    pub fn set_min_climb_grade_pct(&mut self, percent: f64) {
+        self.min_climb_grade_pct = percent;
+    }
+
+    /// The shallowest average grade, in percent, for a climb to be reported.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn min_climb_grade_pct(&self) -> f64 {
+        self.min_climb_grade_pct
+    }
+
+    /// Sets how many samples the analysis's elevation profile has; zero leaves it out.
+    pub fn set_profile_samples(&mut self, samples: usize) {
+        self.profile_samples = samples;
+    }
+
+    /// How many samples the analysis's elevation profile has; zero when it is left out.
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn profile_samples(&self) -> usize {
+        self.profile_samples
+    }
+}
+
+/// Distance, time and speed figures for a whole GPX file.
+#[derive(Debug, Clone, Default)]
+pub struct RideStats {
+    pub total_distance_m: f64,             // Distance across all tracks
+    pub track_distances_m: Vec<f64>,       // Distance of each track, in file order
+    pub elapsed_time_s: Option<f64>,       // First to last timestamp
+    pub moving_time_s: Option<f64>,        // Time spent above the moving threshold
+    pub average_moving_speed_mps: Option<f64>, // Moving distance over moving time
+    pub max_speed_mps: Option<f64>,        // Fastest speed over a short window
+    pub elevation: Option<ElevationChange>, // Gain and loss, if any point has an elevation
+}
+
+/// Cumulative climbing and descending for a GPX file, in metres.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ElevationChange {
+    pub gain_raw_m: f64,  // Sum of every rise between consecutive points
+    pub loss_raw_m: f64,  // Sum of every drop between consecutive points
+    pub gain_m: f64,      // Rises after hysteresis filtering
+    pub loss_m: f64,      // Drops after hysteresis filtering
+}
+
+/// Accumulates elevation gain and loss for one segment.
+///
+/// Raw figures sum every difference between consecutive elevations. The
+/// smoothed figures use a dead band: a reference elevation starts at the
+/// segment's first elevation and only moves once the current elevation is
+/// at least `hysteresis_m` above or below it, at which point the whole
+/// difference is counted and the reference jumps to the current elevation.
+/// Jitter smaller than the band is therefore never counted, while a sustained
+/// climb is counted in full except for at most `hysteresis_m` at its top.
+/// Points without an elevation are skipped.
+fn accumulate_elevation_change(elevations: impl Iterator<Item = f64>, hysteresis_m: f64, change: &mut ElevationChange) {
+    let mut previous: Option<f64> = None;
+    let mut reference: Option<f64> = None;
+
+    for elevation in elevations {
+        if let Some(previous) = previous {
+            let delta = elevation - previous;
+            if delta > 0.0 {
+                change.gain_raw_m += delta;
+            } else {
+                change.loss_raw_m -= delta;
+            }
+        }
+        previous = Some(elevation);
+
+        match reference {
+            None => reference = Some(elevation),
+            Some(base) => {
+                let delta = elevation - base;
+                if delta.abs() >= hysteresis_m {
+                    if delta > 0.0 {
+                        change.gain_m += delta;
+                    } else {
+                        change.loss_m -= delta;
+                    }
+                    reference = Some(elevation);
+                }
+            }
+        }
+    }
+}
+
+/// Seconds since the Unix epoch for a waypoint timestamp.
+fn epoch_seconds(time: gpx::Time) -> f64 {
+    let time: time::OffsetDateTime = time.into();
+    time.unix_timestamp_nanos() as f64 / 1e9
+}
+
+/// `(lat, lon)` pair for a waypoint.
+fn lat_lon(point: &gpx::Waypoint) -> (f64, f64) {
+    (point.point().y(), point.point().x())
+}
+
+/// Haversine length of a segment in metres.
+fn segment_distance_m(segment: &TrackSegment) -> f64 {
+    segment.points.windows(2).map(|pair| geo::haversine_m(lat_lon(&pair[0]), lat_lon(&pair[1]))).sum()
+}
+
+/// Computes distance, time and speed statistics for a parsed GPX file.
+///
+/// Points without timestamps still count toward distance but are skipped
+/// for timing, so files without any times report `None` for every time and
+/// speed figure rather than zero.
+///
+/// # Arguments
+/// * `gpx` - The parsed GPX structure
+/// * `options` - Thresholds for moving time and elevation smoothing
+///
+/// # Returns
+/// * `RideStats` - The computed statistics
+pub fn ride_stats(gpx: &Gpx, options: &AnalysisOptions) -> RideStats {
+    let track_distances_m: Vec<f64> = gpx.tracks.iter()
+        .map(|track| track.segments.iter().map(segment_distance_m).sum())
+        .collect();
+
+    let mut first_time = f64::INFINITY;
+    let mut last_time = f64::NEG_INFINITY;
+    let mut moving_time_s = 0.0;
+    let mut moving_distance_m = 0.0;
+    let mut max_speed_mps: Option<f64> = None;
+    let mut elevation = ElevationChange::default();
+    let mut has_elevation = false;
+
+    for segment in gpx.tracks.iter().flat_map(|track| &track.segments) {
+        has_elevation |= segment.points.iter().any(|point| point.elevation.is_some());
+        accumulate_elevation_change(
+            segment.points.iter().filter_map(|point| point.elevation),
+            options.elevation_hysteresis_m,
+            &mut elevation,
+        );
+
+        // Timestamped points with their cumulative distance along the segment
+        let mut timed: Vec<(f64, f64)> = Vec::with_capacity(segment.points.len());
+        let mut cumulative_m = 0.0;
+        for (i, point) in segment.points.iter().enumerate() {
+            if i > 0 {
+                cumulative_m += geo::haversine_m(lat_lon(&segment.points[i - 1]), lat_lon(point));
+            }
+            if let Some(time) = point.time {
+                timed.push((epoch_seconds(time), cumulative_m));
+            }
+        }
+
+        for &(time, _) in &timed {
+            first_time = first_time.min(time);
+            last_time = last_time.max(time);
+        }
+
+        // Moving time: intervals whose average speed clears the threshold
+        for pair in timed.windows(2) {
+            let (dt, distance) = (pair[1].0 - pair[0].0, pair[1].1 - pair[0].1);
+            if dt > 0.0 && distance / dt >= options.moving_speed_threshold_mps {
+                moving_time_s += dt;
+                moving_distance_m += distance;
+            }
+        }
+
+        // Max speed: for each point, the shortest window ending there that spans the minimum duration
+        let mut start = 0;
+        for end in 0..timed.len() {
+            while start + 1 < end && timed[end].0 - timed[start + 1].0 >= MAX_SPEED_WINDOW_S {
+                start += 1;
+            }
+            let dt = timed[end].0 - timed[start].0;
+            if dt >= MAX_SPEED_WINDOW_S {
+                let speed = (timed[end].1 - timed[start].1) / dt;
+                max_speed_mps = Some(max_speed_mps.map_or(speed, |max| max.max(speed)));
+            }
+        }
+    }
+
+    let has_times = first_time.is_finite();
+    RideStats {
+        total_distance_m: track_distances_m.iter().sum(),
+        track_distances_m,
+        elapsed_time_s: has_times.then_some(last_time - first_time),
+        moving_time_s: has_times.then_some(moving_time_s),
+        average_moving_speed_mps: (moving_time_s > 0.0).then(|| moving_distance_m / moving_time_s),
+        max_speed_mps,
+        elevation: has_elevation.then_some(elevation),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use geo_types::Point;
+    use gpx::{Track, Waypoint};
+
+    use super::*;
+    use crate::gpx_processing::payload::to_time;
+
+    /// A single-segment track along the prime meridian from `(lat, ele, time)` triples.
+    fn track(points: &[(f64, Option<f64>, Option<i64>)]) -> Gpx {
+        let mut segment = TrackSegment::new();
+        segment.points = points.iter()
+            .map(|&(lat, elevation, time)| {
+                let mut point = Waypoint::new(Point::new(0.0, lat));
+                point.elevation = elevation;
+                point.time = time.and_then(to_time);
+                point
+            })
+            .collect();
+        let mut track = Track::new();
+        track.segments = vec![segment];
+        Gpx { tracks: vec![track], ..Gpx::default() }
+    }
+
+    /// Elevation change of a track with the given elevations and the default 3 m hysteresis.
+    fn elevation_change(elevations: &[Option<f64>]) -> ElevationChange {
+        let points: Vec<_> = elevations.iter().enumerate().map(|(i, &ele)| (i as f64 * 0.001, ele, None)).collect();
+        ride_stats(&track(&points), &AnalysisOptions::default()).elevation.unwrap()
+    }
+
+    #[test]
+    fn ignores_jitter_below_the_hysteresis() {
+        let change = elevation_change(&[Some(100.0), Some(101.0), Some(100.0), Some(102.0), Some(100.0), Some(101.5)]);
+        assert_eq!(change, ElevationChange { gain_raw_m: 4.5, loss_raw_m: 3.0, gain_m: 0.0, loss_m: 0.0 });
+    }
+
+    #[test]
+    fn counts_a_step_exactly_at_the_hysteresis() {
+        let change = elevation_change(&[Some(100.0), Some(103.0)]);
+        assert_eq!(change, ElevationChange { gain_raw_m: 3.0, loss_raw_m: 0.0, gain_m: 3.0, loss_m: 0.0 });
+
+        let change = elevation_change(&[Some(100.0), Some(97.0)]);
+        assert_eq!(change, ElevationChange { gain_raw_m: 0.0, loss_raw_m: 3.0, gain_m: 0.0, loss_m: 3.0 });
+    }
+
+    #[test]
+    fn moves_the_reference_only_past_the_hysteresis() {
+        // Reference 100; 104 counts +4 and becomes the reference; 103 and 106 stay
+        // within 3 m of it; 101 counts -3
+        let change = elevation_change(&[Some(100.0), Some(102.0), Some(104.0), Some(103.0), Some(106.0), Some(101.0)]);
+        assert_eq!(change, ElevationChange { gain_raw_m: 7.0, loss_raw_m: 6.0, gain_m: 4.0, loss_m: 3.0 });
+    }
+
+    #[test]
+    fn skips_missing_elevations_mid_track() {
+        let change = elevation_change(&[Some(100.0), None, Some(104.0), None, None, Some(101.0), None]);
+        assert_eq!(change, ElevationChange { gain_raw_m: 4.0, loss_raw_m: 3.0, gain_m: 4.0, loss_m: 3.0 });
+
+        let stats = ride_stats(&track(&[(0.0, None, None), (0.001, None, None)]), &AnalysisOptions::default());
+        assert_eq!(stats.elevation, None);
+    }
+
+    #[test]
+    fn measures_distance_along_the_great_circle() {
+        // One degree of latitude on a 6,371,008.8 m sphere is 111,195.08 m
+        let stats = ride_stats(&track(&[(0.0, None, None), (1.0, None, None)]), &AnalysisOptions::default());
+        assert!((stats.total_distance_m - 111_195.08).abs() < 0.01);
+        assert_eq!(stats.track_distances_m, vec![stats.total_distance_m]);
+    }
+
+    #[test]
+    fn leaves_stops_out_of_moving_time() {
+        // About 50 m every 10 s, then a minute stopped with 0.1 m of GPS drift
+        let step = 0.00045;
+        let drift = 0.1 / 111_195.08;
+        let gpx = track(&[
+            (0.0, None, Some(0)),
+            (step, None, Some(10)),
+            (2.0 * step, None, Some(20)),
+            (2.0 * step + drift, None, Some(80)),
+            (3.0 * step, None, Some(90)),
+        ]);
+        let stats = ride_stats(&gpx, &AnalysisOptions::default());
+        let step_m = 111_195.08 * step;
+
+        assert_eq!(stats.elapsed_time_s, Some(90.0));
+        assert_eq!(stats.moving_time_s, Some(30.0));
+        assert!((stats.average_moving_speed_mps.unwrap() - (3.0 * step_m - 0.1) / 30.0).abs() < 1e-3);
+        assert!((stats.max_speed_mps.unwrap() - step_m / 10.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn reports_no_times_for_tracks_without_timestamps() {
+        let stats = ride_stats(&track(&[(0.0, None, None), (0.001, None, None), (0.002, None, None)]), &AnalysisOptions::default());
+        assert!(stats.total_distance_m > 0.0);
+        assert_eq!(stats.elapsed_time_s, None);
+        assert_eq!(stats.moving_time_s, None);
+        assert_eq!(stats.average_moving_speed_mps, None);
+        assert_eq!(stats.max_speed_mps, None);
+    }
+}
@@ -0,0 +1,198 @@
+//! Payload Verification Module
+//!
+//! This module checks a built payload against the file it came from before
+//! it is uploaded:
+//! 1. Decode the compressed payload back into reduced data
+//! 2. Check every track, segment, route, waypoint and point survived (counts
+//!    match the reduction)
+//! 3. Check the decoded data is identical to the reduction that was encoded
+//! 4. Check each decoded point lies within rounding tolerance of the original
+//!    point it was reduced from
+//!
+//! The largest horizontal distance between a decoded point and its original
+//! is reported in metres, so a precision bug shows up as a number rather than
+//! only as a failed check.
+
+use gpx::{Gpx, Waypoint};  // GPX parsing and representation
+
+use super::{binary::MAX_DECIMALS, geo, payload, reduce::{ReduceOptions, ReduceReport}};
+use crate::SmlrGpx;
+
+/// Outcome of verifying a payload.
+#[derive(Debug, Clone, Default)]
+pub struct PayloadCheck {
+    pub decompressed_size: usize,        // Size of the payload after decompression, zero if it failed
+    pub valid: bool,                     // Whether every check passed
+    pub max_deviation_m: Option<f64>,    // Largest distance between a decoded and an original point
+    pub error: Option<String>,           // The first check that failed, if any
+}
+
+/// Largest difference rounding to `decimals` places can introduce, with room for float error.
+fn rounding_tolerance(decimals: u32) -> f64 {
+    0.5 * 10f64.powi(-(decimals as i32)) * (1.0 + 1e-9) + 1e-12
+}
+
+/// Decodes a payload and checks it against the original GPX and its reduction.
+///
+/// # Arguments
+/// * `payload_bytes` - The compressed payload as it will be stored
+/// * `reduced_gpx` - The reduced GPX the payload was built from
+/// * `original_gpx` - The parsed GPX the payload was built from
+/// * `report` - The reduction report, naming which original points were kept
+/// * `options` - The options the reduction used, which set the rounding tolerances
+///
+/// # Returns
+/// * `PayloadCheck` - Whether the payload is sound and how far it strays from the original
+pub fn verify_payload(
+    payload_bytes: &[u8],
+    reduced_gpx: &SmlrGpx,
+    original_gpx: &Gpx,
+    report: &ReduceReport,
+    options: &ReduceOptions,
+) -> PayloadCheck {
+    let decoded = match payload::decode_reduced(payload_bytes) {
+        Ok(decoded) => decoded,
+        Err(e) => return PayloadCheck { error: Some(e.to_string()), ..PayloadCheck::default() },
+    };
+
+    let mut check = PayloadCheck {
+        decompressed_size: decoded.decompressed_size,
+        valid: false,
+        max_deviation_m: None,
+        error: None,
+    };
+
+    let expected_points: usize = report.kept_indices.iter().flatten().map(Vec::len).sum::<usize>()
+        + report.route_kept_indices.iter().map(Vec::len).sum::<usize>()
+        + original_gpx.waypoints.len();
+    let decoded_points = decoded.reduced.point_count();
+    let shape_matches = decoded.reduced.trk.len() == report.kept_indices.len()
+        && decoded.reduced.trk.iter().zip(&report.kept_indices).all(|(track, kept)| {
+            track.trkseg.len() == kept.len()
+                && track.trkseg.iter().zip(kept).all(|(segment, indices)| segment.trkpt.len() == indices.len())
+        })
+        && decoded.reduced.rte.len() == report.route_kept_indices.len()
+        && decoded.reduced.rte.iter().zip(&report.route_kept_indices).all(|(route, indices)| route.rtept.len() == indices.len())
+        && decoded.reduced.wpt.len() == original_gpx.waypoints.len();
+    if !shape_matches {
+        check.error = Some(format!("Point count mismatch: expected {}, decoded {}", expected_points, decoded_points));
+        return check;
+    }
+
+    if decoded.reduced != *reduced_gpx {
+        check.error = Some("Decoded payload differs from the reduced GPX".to_string());
+    }
+
+    let coordinate_tolerance = rounding_tolerance(options.coordinate_decimals());
+    let elevation_tolerance = rounding_tolerance(options.elevation_decimals().min(u32::from(MAX_DECIMALS)));
+    let mut max_deviation_m: f64 = 0.0;
+
+    // Checks one decoded point against its original, keeping the worst horizontal deviation
+    let mut within_tolerance = |original: &Waypoint, lat: f64, lon: f64, ele: Option<f64>| {
+        let (original_lat, original_lon) = (original.point().y(), original.point().x());
+        max_deviation_m = max_deviation_m.max(geo::haversine_m((original_lat, original_lon), (lat, lon)));
+
+        // Interpolated elevations have no original to compare against
+        let elevation_ok = match (original.elevation, ele) {
+            (Some(expected), Some(actual)) => (expected - actual).abs() <= elevation_tolerance,
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        let coordinates_ok = (original_lat - lat).abs() <= coordinate_tolerance
+            && (original_lon - lon).abs() <= coordinate_tolerance;
+        coordinates_ok && elevation_ok
+    };
+    let mut failure: Option<String> = None;
+
+    let tracks = decoded.reduced.trk.iter().zip(&original_gpx.tracks).zip(&report.kept_indices);
+    for (track_index, ((track, original_track), kept)) in tracks.enumerate() {
+        let segments = track.trkseg.iter().zip(&original_track.segments).zip(kept);
+        for (segment_index, ((segment, original_segment), indices)) in segments.enumerate() {
+            for (point, &index) in segment.trkpt.iter().zip(indices) {
+                if !within_tolerance(&original_segment.points[index], point.lat, point.lon, point.ele) {
+                    failure.get_or_insert_with(|| format!("Point {} of track {} segment {}", index, track_index, segment_index));
+                }
+            }
+        }
+    }
+
+    let routes = decoded.reduced.rte.iter().zip(&original_gpx.routes).zip(&report.route_kept_indices);
+    for (route_index, ((route, original_route), indices)) in routes.enumerate() {
+        for (point, &index) in route.rtept.iter().zip(indices) {
+            if !within_tolerance(&original_route.points[index], point.lat, point.lon, point.ele) {
+                failure.get_or_insert_with(|| format!("Point {} of route {}", index, route_index));
+            }
+        }
+    }
+
+    for (index, (waypoint, original)) in decoded.reduced.wpt.iter().zip(&original_gpx.waypoints).enumerate() {
+        if !within_tolerance(original, waypoint.lat, waypoint.lon, waypoint.ele) {
+            failure.get_or_insert_with(|| format!("Waypoint {}", index));
+        }
+    }
+
+    if let Some(failure) = failure.filter(|_| check.error.is_none()) {
+        check.error = Some(format!("{} is outside the rounding tolerance", failure));
+    }
+
+    check.max_deviation_m = Some(max_deviation_m);
+    check.valid = check.error.is_none();
+    check
+}
+
+#[cfg(test)]
+mod tests {
+    use geo_types::Point;
+    use gpx::{Track, TrackSegment};
+
+    use super::*;
+    use crate::gpx_processing::reduce::reduce_gpx;
+
+    /// A short track, its reduction and the payload built from it.
+    fn payload() -> (Gpx, SmlrGpx, ReduceReport, ReduceOptions, Vec<u8>) {
+        let mut segment = TrackSegment::new();
+        segment.points = (0..50)
+            .map(|i| {
+                let mut point = Waypoint::new(Point::new(-85.6 + i as f64 * 0.001, 42.9 + (i % 7) as f64 * 0.0003));
+                point.elevation = Some(200.0 + (i % 5) as f64);
+                point
+            })
+            .collect();
+        let mut track = Track::new();
+        track.segments = vec![segment];
+        let gpx = Gpx { tracks: vec![track], ..Gpx::default() };
+
+        let options = ReduceOptions::default();
+        let (reduced, report) = reduce_gpx(&gpx, None, &options);
+        let bytes = payload::build_payload(&reduced, &options).unwrap().bytes;
+        (gpx, reduced, report, options, bytes)
+    }
+
+    #[test]
+    fn accepts_an_intact_payload() {
+        let (gpx, reduced, report, options, bytes) = payload();
+        let check = verify_payload(&bytes, &reduced, &gpx, &report, &options);
+        assert!(check.valid, "{:?}", check.error);
+        assert!(check.decompressed_size > 0);
+        assert!(check.max_deviation_m.unwrap() < 1.0);
+    }
+
+    #[test]
+    fn rejects_a_truncated_payload() {
+        let (gpx, reduced, report, options, bytes) = payload();
+        let check = verify_payload(&bytes[..bytes.len() / 2], &reduced, &gpx, &report, &options);
+        assert!(!check.valid);
+        assert!(check.error.is_some());
+        assert_eq!(check.decompressed_size, 0);
+    }
+
+    #[test]
+    fn rejects_a_corrupted_payload() {
+        let (gpx, reduced, report, options, mut bytes) = payload();
+        let middle = bytes.len() / 2;
+        bytes[middle] ^= 0xff;
+        let check = verify_payload(&bytes, &reduced, &gpx, &report, &options);
+        assert!(!check.valid);
+        assert!(check.error.is_some());
+    }
+}
@@ -2,60 +2,148 @@
 //!
 //! This WebAssembly module provides functionality for processing GPX (GPS Exchange Format) files:
 //! - Reducing file size by simplifying track points
-//! - Compressing GPX data using gzip
+//! - Compressing GPX data using gzip, deflate, zlib or brotli
 //! - Analyzing GPX files for metadata and statistics
 //! - Decompressing previously compressed GPX files
+//! - Importing FIT activities recorded by bike computers
 //!
 //! The module is designed to be used in web applications via WebAssembly.
+//! The JavaScript bindings live behind the default `wasm` feature; without it
+//! the crate is a plain Rust library, which the `gpx-processor` CLI uses for
+//! batch processing on the command line.
 
 // Standard library imports
-use std::{collections::HashMap, io::Read};
+use std::collections::HashMap;
 
 // External crate imports
-use flate2::bufread::GzDecoder;                // Decompression functionality
 use gpx::Gpx;                                  // GPX parsing and representation
-use js_sys;                                    // JavaScript interop utilities
 use serde::{Deserialize, Serialize};           // Serialization framework
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;                  // WebAssembly bindings
-// use web_sys::console;                          // Logging to browser console
 
 // Local module imports
+#[cfg(feature = "wasm")]
+mod document; // Stateful GPX document exposed to JavaScript
+mod error; // Typed errors with machine-readable codes
 mod gpx_processing; // Module for GPX processing
 // mod geo_tiff; // Module for GeoTIFF processing
+#[cfg(feature = "wasm")]
 mod logging;
+mod timing; // Wall-clock timing for browser and native builds
+
+#[cfg(feature = "wasm")]
+pub use document::GpxDocument;
+pub use error::{GpxProcessorError, Result};
+pub use gpx_processing::binary::{decode_smlr_gpx, encode_smlr_gpx};
+pub use gpx_processing::climbs::{Climb, ClimbCategory};
+pub use gpx_processing::compress::{compress_bytes, decompress_bytes, CompressionCodec, CompressionOptions};
+pub use gpx_processing::fit::{decode_fit, is_fit, FitActivity, SensorSample, SensorSamples, SensorStats, SensorSummary};
+pub use gpx_processing::grade::GradeBucket;
+pub use gpx_processing::input::{decode_gpx_bytes, decode_upload, Upload, MAX_GPX_BYTES, MAX_GPX_POINTS};
+pub use gpx_processing::metadata::RideMetadata;
+pub use gpx_processing::minify::minify;
+pub use gpx_processing::payload::{build_payload, decode_reduced, DecodedPayload, Payload};
+pub use gpx_processing::pipeline::{process_fit, process_gpx, process_upload, ProcessedGpx};
+pub use gpx_processing::polyline::{decode_polylines, encode_polylines, EncodedPolylines, PolylinePrecision};
+pub use gpx_processing::profile::{ElevationProfile, ProfileSample, MAX_PROFILE_SAMPLES};
+pub use gpx_processing::reduce::{
+    reduce_gpx_size, reduce_gpx_size_with_options, reduce_upload_with_options, CoordinatePrecision, PayloadFormat, ReduceOptions,
+    SimplifyMode,
+};
+pub use gpx_processing::stats::AnalysisOptions;
 
 /// Simplified GPX structure for serialization and compression.
 ///
 /// This structure maintains only the essential elements of a GPX file
-/// to reduce size while preserving the core geospatial data.
-#[derive(Debug, Serialize, Deserialize)]
+/// to reduce size while preserving the core geospatial data. Metadata,
+/// waypoints and routes are omitted from the serialized form when absent.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct SmlrGpx {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    metadata: Option<RideMetadata>,  // Name, description and other details of the file
     trk: Vec<SmlrTrack>,  // Collection of tracks in the GPX file
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    wpt: Vec<SmlrWaypoint>,  // Waypoints, such as trailheads and water stops
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    rte: Vec<SmlrRoute>,  // Planned routes
+}
+
+impl SmlrGpx {
+    /// Total number of track points, route points and waypoints.
+    fn point_count(&self) -> usize {
+        let track_points: usize = self.trk.iter().flat_map(|track| &track.trkseg).map(|segment| segment.trkpt.len()).sum();
+        let route_points: usize = self.rte.iter().map(|route| route.rtept.len()).sum();
+        track_points + route_points + self.wpt.len()
+    }
 }
 
 /// Simplified representation of a GPX track.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 struct SmlrTrack {
     trkseg: Vec<SmlrTrackSegment>,  // Segments within this track
 }
 
 /// Simplified representation of a track segment.
-#[derive(Debug, Serialize, Deserialize)]
+///
+/// When times are kept, `t0` is the segment's start epoch and each point
+/// stores its time as a whole-second offset from it in `dt`.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 struct SmlrTrackSegment {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    t0: Option<i64>,             // Unix time in seconds of the first timed point, if times are kept
     trkpt: Vec<SmlrTrackPoint>,  // Track points within this segment
 }
 
+/// Simplified representation of a planned route.
+///
+/// Route points are reduced like a track segment, including `t0`/`dt`
+/// timestamps when times are kept and the route has them.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct SmlrRoute {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    t0: Option<i64>,             // Unix time in seconds of the first timed point, if times are kept
+    rtept: Vec<SmlrTrackPoint>,  // Route points, in travel order
+}
+
+/// Simplified representation of a waypoint: its position, name and map symbol.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct SmlrWaypoint {
+    #[serde(rename = "@lat")]
+    lat: f64,              // Latitude in decimal degrees
+    #[serde(rename = "@lon")]
+    lon: f64,              // Longitude in decimal degrees
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ele: Option<f64>,      // Optional elevation in meters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,  // Optional name, trimmed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sym: Option<String>,   // Optional map symbol, e.g. "Flag" or "Drinking Water"
+}
+
 /// Simplified representation of a track point with only essential data.
 ///
 /// Maintains latitude, longitude, and optional elevation while
-/// removing timestamps and other metadata to reduce size.
-#[derive(Debug, Serialize, Deserialize)]
+/// removing other metadata to reduce size. Timestamps are dropped unless
+/// requested, in which case they are stored relative to the segment's `t0`.
+/// Heart rate, cadence and power are kept for FIT uploads that recorded them.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 struct SmlrTrackPoint {
     #[serde(rename = "@lat")]
     lat: f64,           // Latitude in decimal degrees
     #[serde(rename = "@lon")]
     lon: f64,           // Longitude in decimal degrees
-    ele: Option<f64>,   // Optional elevation in meters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ele: Option<f64>,   // Optional elevation in meters, omitted when unknown
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    grade: Option<f64>, // Optional smoothed grade in percent, present only when requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dt: Option<i64>,    // Optional seconds since the segment's `t0`, present only when times are kept
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hr: Option<u16>,    // Optional heart rate in beats per minute
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cad: Option<u16>,   // Optional pedalling cadence in revolutions per minute
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pwr: Option<u16>,   // Optional power in watts
 }
 
 /// Comprehensive analysis of a GPX file including size metrics and geographical information.
@@ -68,17 +156,39 @@ pub struct GpxAnalysis {
     reduced_size_bytes: usize,          // Size after point reduction in bytes
     compressed_size_bytes: usize,       // Size after compression in bytes
     compression_ratio: f64,             // Ratio of compression (0.0-1.0, higher is better)
-    point_count: usize,                 // Number of track points in original file
-    reduced_point_count: usize,         // Number of track points after reduction
+    point_count: usize,                 // Number of track points, route points and waypoints in original file
+    reduced_point_count: usize,         // Number of points after reduction
     point_reduction_ratio: f64,         // Ratio of point reduction (0.0-1.0)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    metadata: Option<RideMetadata>,     // Name, description, activity type, start time and creator
     tracks_count: usize,                // Number of tracks in the file
     segments_count: usize,              // Total number of track segments
+    routes_count: usize,                // Number of planned routes in the file
+    waypoints_count: usize,             // Number of waypoints in the file
     elevation_range: Option<(f64, f64)>, // Min and max elevation if available
+    elevation_gain_m: Option<f64>,      // Total ascent after hysteresis filtering
+    elevation_loss_m: Option<f64>,      // Total descent after hysteresis filtering
+    elevation_gain_raw_m: Option<f64>,  // Total ascent summing every point-to-point rise
+    elevation_loss_raw_m: Option<f64>,  // Total descent summing every point-to-point drop
+    climbs: Vec<Climb>,                 // Sustained climbs with grades and categories
+    grade_histogram: Vec<GradeBucket>,  // Share of distance in each grade band
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    elevation_profile: Option<ElevationProfile>, // Resampled elevation chart, when requested
     bounding_box: Option<BoundingBox>,  // Geographical bounds of the GPX data
+    total_distance_m: f64,              // Distance across all tracks in metres
+    track_distances_m: Vec<f64>,        // Distance of each track in metres
+    elapsed_time_s: Option<f64>,        // First to last timestamp, if the file has times
+    moving_time_s: Option<f64>,         // Time spent above the moving speed threshold
+    average_moving_speed_mps: Option<f64>, // Average speed while moving
+    max_speed_mps: Option<f64>,         // Fastest speed over a short window
+    simplification_error_m: f64,        // Max distance of a dropped point from the simplified line
     timing_ms: HashMap<String, f64>,    // Performance metrics for processing steps
-    decompressed_size_bytes: usize,     // Size of decompressed GPX data
-    decompressed_valid: bool,           // Whether decompressed data is valid GPX
-    decompressed_error: Option<String>, // Error message if decompression failed
+    decompressed_size_bytes: usize,     // Size of the payload after decompression
+    decompressed_valid: bool,           // Whether the payload decodes to the same reduced GPX
+    decompressed_error: Option<String>, // First verification failure, if any
+    decompressed_max_deviation_m: Option<f64>, // Largest distance from a decoded point to its original
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sensors: Option<SensorSummary>,     // Heart rate, cadence and power, for FIT uploads that recorded them
 }
 
 /// Geographical bounding box for the GPX data.
@@ -103,8 +213,14 @@ pub struct BoundingBox {
 /// @param gpx_string - The GPX content to validate
 /// @returns Whether the input is valid GPX data
 pub fn validate_gpx(gpx_string: &str) -> bool {
+    // Attempt to parse as GPX (most thorough validation)
+    has_gpx_structure(gpx_string) && parse_gpx_from_string(gpx_string).is_ok()
+}
+
+/// Performs the cheap size and structure checks of [`validate_gpx`] without parsing.
+fn has_gpx_structure(gpx_string: &str) -> bool {
     // Check for reasonable size
-    if gpx_string.len() > 50_000_000 { // 50MB max
+    if gpx_string.len() > gpx_processing::input::MAX_GPX_BYTES { // 50MB max
         return false;
     }
     
@@ -114,16 +230,7 @@ pub fn validate_gpx(gpx_string: &str) -> bool {
     }
     
     // Check for GPX tag
-    if !gpx_string.contains("<gpx") {
-        return false;
-    }
-    // console::log_1(&JsValue::from_str("parsing gpx from string in validate_gpx"));
-
-    // Attempt to parse as GPX (most thorough validation)
-    match parse_gpx_from_string(gpx_string) {
-        Ok(_) => true,
-        Err(_) => false
-    }
+    gpx_string.contains("<gpx")
 }
 
 /// Analyzes a GPX file string and returns detailed metrics and statistics.
@@ -132,260 +239,78 @@ pub fn validate_gpx(gpx_string: &str) -> bool {
 /// - Size metrics (original, reduced, and compressed sizes)
 /// - Track point statistics (count, reduction)
 /// - Geographical information (elevation range, bounding box)
+/// - Ride statistics (distance, elapsed and moving time, speeds, elevation gain and loss, climbs, grade histogram)
+/// - An optional resampled elevation profile for charting
 /// - Performance metrics for each processing step
+/// - Decompression and integrity check of the compressed output
 ///
 /// # Arguments
 /// * `gpx_string` - The raw GPX file content as a string
 ///
 /// # Returns
-/// * `Result<JsValue, JsValue>` - A JavaScript object containing analysis data or an error
-
-// Helper: Analyze size and format
-// #[wasm_bindgen]
-// pub fn analyze_gpx_size(data: &[u8]) -> (usize, String) {
-//     let as_str = std::str::from_utf8(data).ok();
-//     if let Some(s) = as_str {
-//         if s.trim_start().starts_with("<?xml") && s.contains("<gpx") {
-//             (data.len(), "Raw GPX XML".to_string())
-//         } else if s.contains("\"trk\"") && s.contains("\"trkseg\"") && s.contains("\"trkpt\"") {
-//             (data.len(), "Reduced GPX JSON".to_string())
-//         } else {
-//             (data.len(), "Unknown text format".to_string())
-//         }
-//     } else {
-//         (data.len(), "Compressed GPX binary".to_string())
-//     }
-// }
-
-// Helper: Validate GPX XML
-fn is_valid_gpx_xml(gpx_string: &str) -> bool {
-    validate_gpx(gpx_string)
-}
-
-// Helper: Decompress GPX binary
-fn decompress_gpx_binary(compressed_data: &[u8]) -> Result<String, JsValue> {
-    let mut decoder = GzDecoder::new(compressed_data);
-    
-    let mut decompressed_data = String::new();
-    
-    decoder.read_to_string(&mut decompressed_data)
-        .map_err(|e| JsValue::from_str(&format!("Decompression error: {}", e)))?;
-    
-    // console::log_1(&JsValue::from_str("parsing gpx from string in decompress_gpx_binary"));
-
-    
-    let parsed_gpx = parse_gpx_from_string(&decompressed_data)?;
-    
-    let decompressed_gpx_xml = write_gpx_from_parsed_gpx_string(parsed_gpx)?;
-    
-    Ok(decompressed_gpx_xml)
+/// * `Result<JsValue>` - A JavaScript object containing analysis data or an error
+///
+/// # Errors
+/// Thrown in JavaScript as an `Error` whose `code` property is one of:
+/// * `TOO_LARGE` or `TOO_MANY_POINTS` if the file exceeds the processing limits
+/// * `PARSE_ERROR` if the GPX cannot be parsed
+/// * `SERIALIZATION` or `COMPRESSION` if building the result fails
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+pub fn analyze_gpx(gpx_string: &str) -> Result<JsValue> {
+    analyze_gpx_with_options(gpx_string, &ReduceOptions::default(), &AnalysisOptions::default())
 }
 
-/// Analyzes a GPX file string and returns detailed metrics and statistics, including decompression and integrity check.
+/// Analyzes a GPX file string as [`analyze_gpx`] does, with caller-supplied options.
+///
+/// Use this to preview how a given precision or simplification setting
+/// affects the reduced point count and compressed size, or to change what
+/// counts as moving in the ride statistics.
+///
+/// # Arguments
+/// * `gpx_string` - The raw GPX file content as a string
+/// * `options` - Precision and simplification settings for the reduction step
+/// * `analysis_options` - Thresholds for the ride statistics
+///
+/// # Returns
+/// * `Result<JsValue>` - A JavaScript object containing analysis data or an error
+///
+/// # Errors
+/// Thrown in JavaScript as an `Error` whose `code` property is one of:
+/// * `TOO_LARGE` or `TOO_MANY_POINTS` if the file exceeds the processing limits
+/// * `PARSE_ERROR` if the GPX cannot be parsed
+/// * `SERIALIZATION` or `COMPRESSION` if building the result fails
+#[cfg(feature = "wasm")]
 #[wasm_bindgen]
-pub fn analyze_gpx(gpx_string: &str) -> Result<JsValue, JsValue> {
-    let mut timings = HashMap::new();
-    let start_time = js_sys::Date::now();
-        // console::log_1(&JsValue::from_str("parsing gpx from string in analyze_gpx"));
-
-    // Parse the original GPX file
-    let original_gpx = match parse_gpx_from_string(gpx_string) {
-        Ok(gpx) => gpx,
-        Err(e) => return Err(JsValue::from_str(&format!("Error parsing GPX: {}", e))),
-    };
-    timings.insert("parsing".to_string(), js_sys::Date::now() - start_time);
-
-    // Extract basic metrics from the original GPX file
-    let original_size = gpx_string.len();
-    let original_point_count = count_points(&original_gpx);
-    let tracks_count = original_gpx.tracks.len();
-    let segments_count = original_gpx.tracks.iter().map(|track| track.segments.len()).sum();
-    let elevation_range = calculate_elevation_range(&original_gpx);
-    let bounding_box = calculate_bounding_box(&original_gpx);
-
-    // Reduce the GPX file size by simplifying track points and add <gpx> tag wrapper back to content
-    let reduce_start = js_sys::Date::now();
-    let reduced_gpx_string = match gpx_processing::reduce::reduce_gpx_size(gpx_string) {
-        Ok(reduced) => format!("<gpx>{}</gpx>",reduced),
-        Err(e) => return Err(e),
-    };
-    timings.insert("reduction".to_string(), js_sys::Date::now() - reduce_start);
-
-    // Parse the reduced GPX to extract metrics
-    let reduced_gpx: SmlrGpx = match serde_json::from_str(&reduced_gpx_string) {
-        Ok(gpx) => gpx,
-        Err(e) => return Err(JsValue::from_str(&format!("Error parsing reduced GPX: {}", e))),
-    };
-    let reduced_point_count = reduced_gpx.trk.iter().flat_map(|track| &track.trkseg).map(|segment| segment.trkpt.len()).sum();
-
-    // Compress the reduced GPX
-    let compress_start = js_sys::Date::now();
-    let compressed_gpx = match gpx_processing::compress::compress_gpx(&reduced_gpx_string) {
-        Ok(compressed) => compressed,
-        Err(e) => return Err(e),
-    };
-    timings.insert("compression".to_string(), js_sys::Date::now() - compress_start);
-
-    let reduced_size = reduced_gpx_string.len();
-    let compressed_size = compressed_gpx.len();
-    let compression_ratio = if original_size > 0 {
-        1.0 - (compressed_size as f64 / original_size as f64)
-    } else {
-        0.0
-    };
-    let point_reduction_ratio = if original_point_count > 0 {
-        1.0 - (reduced_point_count as f64 / original_point_count as f64)
-    } else {
-        0.0
-    };
-
-    // Decompress and check integrity
-    let (decompressed_size, decompressed_valid, decompressed_error) = match decompress_gpx_binary(&compressed_gpx) {
-        Ok(decompressed_gpx) => {
-            let size = decompressed_gpx.len();
-            let valid = is_valid_gpx_xml(&decompressed_gpx);
-            (size, valid, None)
-        },
-        Err(e) => (0, false, Some(e.as_string().unwrap_or_else(|| "Unknown error".to_string()))),
-    };
-
-    // Create the analysis object with all collected metrics
-    let analysis = GpxAnalysis {
-        original_size_bytes: original_size,
-        reduced_size_bytes: reduced_size,
-        compressed_size_bytes: compressed_size,
-        compression_ratio,
-        point_count: original_point_count,
-        reduced_point_count,
-        point_reduction_ratio,
-        tracks_count,
-        segments_count,
-        elevation_range,
-        bounding_box,
-        timing_ms: timings,
-        decompressed_size_bytes: decompressed_size,
-        decompressed_valid,
-        decompressed_error,
-    };
-
-    // Log to browser console
-    // console::log_1(&JsValue::from_str(&format!("GPX analysis: {:?}", analysis)));
+pub fn analyze_gpx_with_options(
+    gpx_string: &str,
+    options: &ReduceOptions,
+    analysis_options: &AnalysisOptions,
+) -> Result<JsValue> {
+    let processed = gpx_processing::pipeline::process_gpx(gpx_string, options, analysis_options)?;
 
     // Serialize to JavaScript value for return
-    match serde_wasm_bindgen::to_value(&analysis) {
-        Ok(js) => Ok(js),
-        Err(e) => Err(JsValue::from_str(&format!("Serialization error: {}", e))),
-    }
+    Ok(serde_wasm_bindgen::to_value(&processed.analysis)?)
 }
-// pub fn analyze_gpx(gpx_string: &str) -> Result<JsValue, JsValue> {
-//     // Initialize timing tracking for performance analysis
-//     let mut timings = HashMap::new();
-//     let start_time = js_sys::Date::now();
 
-    
-    
-//     // Parse the original GPX file
-//     let original_gpx = match parse_gpx_from_string(gpx_string) {
-//         Ok(gpx) => gpx,
-//         Err(e) => return Err(JsValue::from_str(&format!("Error parsing GPX: {}", e))),
-//     };
-    
-//     // Record parsing time
-//     timings.insert("parsing".to_string(), js_sys::Date::now() - start_time);
-    
-//     // Extract basic metrics from the original GPX file
-//     let original_size = gpx_string.len();
-//     let original_point_count = count_points(&original_gpx);
-//     let tracks_count = original_gpx.tracks.len();
-//     let segments_count = original_gpx.tracks.iter()
-//         .map(|track| track.segments.len())
-//         .sum();
-    
-//     // Calculate additional geographical metrics
-//     let elevation_range = calculate_elevation_range(&original_gpx);
-//     let bounding_box = calculate_bounding_box(&original_gpx);
-    
-//     // Reduce the GPX file size by simplifying track points
-//     let reduce_start = js_sys::Date::now();
-//     let reduced_gpx_string = match reduce_gpx_size(gpx_string) {
-//         Ok(reduced) => reduced,
-//         Err(e) => return Err(e),
-//     };
-//     timings.insert("reduction".to_string(), js_sys::Date::now() - reduce_start);
-    
-//     // Parse the reduced GPX to extract metrics
-//     let reduced_gpx: SmlrGpx = match serde_json::from_str(&reduced_gpx_string) {
-//         Ok(gpx) => gpx,
-//         Err(e) => return Err(JsValue::from_str(&format!("Error parsing reduced GPX: {}", e))),
-//     };
-    
-//     // Count points in the reduced GPX
-//     let reduced_point_count = reduced_gpx.trk.iter()
-//         .flat_map(|track| &track.trkseg)
-//         .map(|segment| segment.trkpt.len())
-//         .sum();
-    
-//     // Compress the reduced GPX
-//     let compress_start = js_sys::Date::now();
-//     let compressed_gpx = match compress_gpx(&reduced_gpx_string) {
-//         Ok(compressed) => compressed,
-//         Err(e) => return Err(e),
-//     };
-//     timings.insert("compression".to_string(), js_sys::Date::now() - compress_start);
-    
-//     // Calculate size metrics
-//     let reduced_size = reduced_gpx_string.len();
-//     let compressed_size = compressed_gpx.len();
-    
-//     // Calculate compression ratio (higher is better)
-//     let compression_ratio = if original_size > 0 {
-//         1.0 - (compressed_size as f64 / original_size as f64)
-//     } else {
-//         0.0
-//     };
-    
-//     // Calculate point reduction ratio (higher means more points removed)
-//     let point_reduction_ratio = if original_point_count > 0 {
-//         1.0 - (reduced_point_count as f64 / original_point_count as f64)
-//     } else {
-//         0.0
-//     };
-    
-//     // Create the analysis object with all collected metrics
-//     let analysis = GpxAnalysis {
-//         original_size_bytes: original_size,
-//         reduced_size_bytes: reduced_size,
-//         compressed_size_bytes: compressed_size,
-//         compression_ratio,
-//         point_count: original_point_count,
-//         reduced_point_count,
-//         point_reduction_ratio,
-//         tracks_count,
-//         segments_count,
-//         elevation_range,
-//         bounding_box,
-//         timing_ms: timings,
-//     };
-    
-//     // Serialize to JavaScript value for return
-//     match serde_wasm_bindgen::to_value(&analysis) {
-//         Ok(js) => Ok(js),
-//         Err(e) => Err(JsValue::from_str(&format!("Serialization error: {}", e))),
-//     }
-// }
+/// Iterates over every point in a GPX file: track points, route points and waypoints.
+fn all_points(gpx: &Gpx) -> impl Iterator<Item = &gpx::Waypoint> {
+    gpx.tracks.iter()
+        .flat_map(|track| &track.segments)
+        .flat_map(|segment| &segment.points)
+        .chain(gpx.routes.iter().flat_map(|route| &route.points))
+        .chain(&gpx.waypoints)
+}
 
-/// Counts the total number of track points in a GPX file.
+/// Counts the total number of points in a GPX file.
 ///
 /// # Arguments
 /// * `gpx` - The parsed GPX structure
 ///
 /// # Returns
-/// * `usize` - The total count of track points across all segments and tracks
+/// * `usize` - The total count of track points, route points and waypoints
 fn count_points(gpx: &Gpx) -> usize {
-    gpx.tracks.iter()
-        .flat_map(|track| &track.segments)
-        .map(|segment| segment.points.len())
-        .sum()
+    all_points(gpx).count()
 }
 
 /// Calculates the minimum and maximum elevation values in a GPX file.
@@ -397,9 +322,7 @@ fn count_points(gpx: &Gpx) -> usize {
 /// * `Option<(f64, f64)>` - Tuple of (min_elevation, max_elevation) or None if no elevation data
 fn calculate_elevation_range(gpx: &Gpx) -> Option<(f64, f64)> {
     // Extract all elevation values from the GPX file
-    let elevations: Vec<f64> = gpx.tracks.iter()
-        .flat_map(|track| &track.segments)
-        .flat_map(|segment| &segment.points)
+    let elevations: Vec<f64> = all_points(gpx)
         .filter_map(|point| point.elevation)
         .collect();
     
@@ -431,19 +354,15 @@ fn calculate_bounding_box(gpx: &Gpx) -> Option<BoundingBox> {
     let mut has_points = false;
     
     // Iterate through all points to find min/max values
-    for track in &gpx.tracks {
-        for segment in &track.segments {
-            for point in &segment.points {
-                has_points = true;
-                let lat = point.point().y();
-                let lon = point.point().x();
-                
-                min_lat = min_lat.min(lat);
-                max_lat = max_lat.max(lat);
-                min_lon = min_lon.min(lon);
-                max_lon = max_lon.max(lon);
-            }
-        }
+    for point in all_points(gpx) {
+        has_points = true;
+        let lat = point.point().y();
+        let lon = point.point().x();
+        
+        min_lat = min_lat.min(lat);
+        max_lat = max_lat.max(lat);
+        min_lon = min_lon.min(lon);
+        max_lon = max_lon.max(lon);
     }
     
     // Return None if no points were found
@@ -468,29 +387,40 @@ fn calculate_bounding_box(gpx: &Gpx) -> Option<BoundingBox> {
 /// * `gpx_string` - The raw GPX file content as a string
 ///
 /// # Returns
-/// * `Result<JsValue, JsValue>` - A JavaScript object containing both compressed data and analysis
+/// * `Result<JsValue>` - A JavaScript object containing both compressed data and analysis
+///
+/// # Errors
+/// Thrown in JavaScript as an `Error` whose `code` property is one of:
+/// * `TOO_LARGE` or `TOO_MANY_POINTS` if the file exceeds the processing limits
+/// * `PARSE_ERROR` if the GPX cannot be parsed
+/// * `SERIALIZATION` or `COMPRESSION` if building the result fails
+#[cfg(feature = "wasm")]
 #[wasm_bindgen]
-pub fn process_gpx_with_analytics(gpx_string: &str) -> Result<JsValue, JsValue> {
-    // Analyze the GPX file
-    let analysis_js = analyze_gpx(gpx_string)?;
-    
-    // Reduce and compress the GPX file
-    let compressed_data = reduce_compress_gpx(gpx_string)?;
+pub fn process_gpx_with_analytics(gpx_string: &str) -> Result<JsValue> {
+    // Analyze, reduce and compress the GPX file from a single parse
+    let processed = gpx_processing::pipeline::process_gpx(gpx_string, &ReduceOptions::default(), &AnalysisOptions::default())?;
+    processed_to_js(processed)
+}
+
+/// Packs a pipeline result into a JavaScript object with `analysis` and `data` properties.
+#[cfg(feature = "wasm")]
+fn processed_to_js(processed: gpx_processing::pipeline::ProcessedGpx) -> Result<JsValue> {
+    let analysis_js = serde_wasm_bindgen::to_value(&processed.analysis)?;
     
     // Create a JavaScript object to hold both results
     let result = js_sys::Object::new();
     
     // Add the analysis data to the result object
     js_sys::Reflect::set(&result, &JsValue::from_str("analysis"), &analysis_js)
-        .map_err(|_| JsValue::from_str("Error setting analysis property"))?;
+        .map_err(|_| GpxProcessorError::Serialization("Error setting analysis property".to_string()))?;
     
     // Convert the compressed data to a JavaScript Uint8Array
-    let array = js_sys::Uint8Array::new_with_length(compressed_data.len() as u32);
-    array.copy_from(&compressed_data);
+    let array = js_sys::Uint8Array::new_with_length(processed.payload.len() as u32);
+    array.copy_from(&processed.payload);
     
     // Add the compressed data to the result object
     js_sys::Reflect::set(&result, &JsValue::from_str("data"), &array)
-        .map_err(|_| JsValue::from_str("Error setting data property"))?;
+        .map_err(|_| GpxProcessorError::Serialization("Error setting data property".to_string()))?;
     
     Ok(result.into())
 }
@@ -498,54 +428,233 @@ pub fn process_gpx_with_analytics(gpx_string: &str) -> Result<JsValue, JsValue>
 /// Reduces and compresses a GPX file in one operation.
 ///
 /// This function combines the size reduction and compression steps
-/// into a single operation for efficiency.
+/// into a single operation for efficiency, using the default [`ReduceOptions`].
 ///
 /// # Arguments
 /// * `gpx_string` - The raw GPX file content as a string
 ///
 /// # Returns
-/// * `Result<Vec<u8>, JsValue>` - The compressed binary data or an error
-#[wasm_bindgen]
-pub fn reduce_compress_gpx(gpx_string: &str) -> Result<Vec<u8>, JsValue> {
-    let is_valid = validate_gpx(&gpx_string);
-
-    let _gpx_processing_analysis = analyze_gpx(&gpx_string);
+/// * `Result<Vec<u8>>` - The compressed binary data or an error
+///
+/// # Errors
+/// Thrown in JavaScript as an `Error` whose `code` property is one of:
+/// * `NOT_GPX` if the text is not a GPX document
+/// * `TOO_LARGE` or `TOO_MANY_POINTS` if the file exceeds the processing limits
+/// * `PARSE_ERROR` if the GPX cannot be parsed
+/// * `SERIALIZATION` or `COMPRESSION` if building the result fails
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn reduce_compress_gpx(gpx_string: &str) -> Result<Vec<u8>> {
+    reduce_compress_gpx_with_options(gpx_string, &ReduceOptions::default())
+}
 
-    
-    if !is_valid {
-        return Err(JsValue::from_str(&format!("Incorrect file format")));
+/// Reduces and compresses a GPX file using caller-supplied reduction options.
+///
+/// # Arguments
+/// * `gpx_string` - The raw GPX file content as a string
+/// * `options` - Precision settings for the reduction step
+///
+/// # Returns
+/// * `Result<Vec<u8>>` - The compressed binary data or an error
+///
+/// # Errors
+/// Thrown in JavaScript as an `Error` whose `code` property is one of:
+/// * `NOT_GPX` if the text is not a GPX document
+/// * `TOO_LARGE` or `TOO_MANY_POINTS` if the file exceeds the processing limits
+/// * `PARSE_ERROR` if the GPX cannot be parsed
+/// * `SERIALIZATION` or `COMPRESSION` if building the result fails
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn reduce_compress_gpx_with_options(gpx_string: &str, options: &ReduceOptions) -> Result<Vec<u8>> {
+    // Cheap structural checks first; the pipeline's parse completes the validation
+    if !has_gpx_structure(gpx_string) {
+        return Err(GpxProcessorError::NotGpx);
     }
-    // First reduce the GPX file size by simplifying track points
-    let smlr_gpx = gpx_processing::reduce::reduce_gpx_size(gpx_string)?;
 
-    // Then compress the reduced GPX file
-    let compressed_gpx = gpx_processing::compress::compress_gpx(&smlr_gpx)?;
+    // Reduce and compress the GPX file from a single parse
+    let processed = gpx_processing::pipeline::process_gpx(gpx_string, options, &AnalysisOptions::default())?;
 
-    Ok(compressed_gpx)
+    Ok(processed.payload)
 }
 
-/// Decompresses a previously compressed GPX file.
+/// Analyzes raw uploaded file bytes: GPX XML or a FIT activity, plain or
+/// gzip-compressed (`.gpx.gz`, `.fit.gz`).
 ///
-/// This function reverses the compression process, converting
-/// compressed binary data back to the original GPX XML string.
+/// FIT files also get a heart rate, cadence and power summary in the analysis.
+///
+/// # Arguments
+/// * `data` - The uploaded file bytes
+///
+/// # Returns
+/// * `Result<JsValue>` - A JavaScript object containing analysis data or an error
+///
+/// # Errors
+/// Thrown in JavaScript as an `Error` whose `code` property is one of:
+/// * `TOO_LARGE` or `TOO_MANY_POINTS` if the file exceeds the processing limits
+/// * `DECOMPRESSION` or `INVALID_ENCODING` if the upload cannot be decoded
+/// * `PARSE_ERROR` if the GPX or FIT file is malformed
+/// * `SERIALIZATION` or `COMPRESSION` if building the result fails
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+pub fn analyze_gpx_bytes(data: &[u8]) -> Result<JsValue> {
+    let processed = gpx_processing::pipeline::process_upload(data, &ReduceOptions::default(), &AnalysisOptions::default())?;
+    Ok(serde_wasm_bindgen::to_value(&processed.analysis)?)
+}
+
+/// Processes raw uploaded file bytes, GPX or FIT and plain or gzip-compressed,
+/// returning both the compressed data and analysis metrics.
+///
+/// # Arguments
+/// * `data` - The uploaded file bytes
+///
+/// # Returns
+/// * `Result<JsValue>` - A JavaScript object containing both compressed data and analysis
+///
+/// # Errors
+/// Thrown in JavaScript as an `Error` whose `code` property is one of:
+/// * `TOO_LARGE` or `TOO_MANY_POINTS` if the file exceeds the processing limits
+/// * `DECOMPRESSION` or `INVALID_ENCODING` if the upload cannot be decoded
+/// * `PARSE_ERROR` if the GPX or FIT file is malformed
+/// * `SERIALIZATION` or `COMPRESSION` if building the result fails
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+pub fn process_gpx_bytes_with_analytics(data: &[u8]) -> Result<JsValue> {
+    processed_to_js(gpx_processing::pipeline::process_upload(data, &ReduceOptions::default(), &AnalysisOptions::default())?)
+}
+
+/// Reduces and compresses raw uploaded file bytes: GPX XML or a FIT activity,
+/// plain or gzip-compressed (`.gpx.gz`, `.fit.gz`).
+///
+/// Gzip input is detected by its magic bytes and decompressed with the
+/// 50MB cap enforced while streaming; FIT files are detected by their header.
+///
+/// # Arguments
+/// * `data` - The uploaded file bytes
+///
+/// # Returns
+/// * `Result<Vec<u8>>` - The compressed binary data or an error
+///
+/// # Errors
+/// Thrown in JavaScript as an `Error` whose `code` property is one of:
+/// * `NOT_GPX` if the text is not a GPX document
+/// * `TOO_LARGE` or `TOO_MANY_POINTS` if the file exceeds the processing limits
+/// * `DECOMPRESSION` or `INVALID_ENCODING` if the upload cannot be decoded
+/// * `PARSE_ERROR` if the GPX or FIT file is malformed
+/// * `SERIALIZATION` or `COMPRESSION` if building the result fails
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn reduce_compress_gpx_bytes(data: &[u8]) -> Result<Vec<u8>> {
+    reduce_compress_gpx_bytes_with_options(data, &ReduceOptions::default())
+}
+
+/// Reduces and compresses raw uploaded file bytes using caller-supplied reduction options.
+///
+/// # Arguments
+/// * `data` - The uploaded GPX or FIT file bytes, plain or gzip-compressed
+/// * `options` - Precision and simplification settings for the reduction step
+///
+/// # Returns
+/// * `Result<Vec<u8>>` - The compressed binary data or an error
+///
+/// # Errors
+/// Thrown in JavaScript as an `Error` whose `code` property is one of:
+/// * `NOT_GPX` if the text is not a GPX document
+/// * `TOO_LARGE` or `TOO_MANY_POINTS` if the file exceeds the processing limits
+/// * `DECOMPRESSION` or `INVALID_ENCODING` if the upload cannot be decoded
+/// * `PARSE_ERROR` if the GPX or FIT file is malformed
+/// * `SERIALIZATION` or `COMPRESSION` if building the result fails
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn reduce_compress_gpx_bytes_with_options(data: &[u8], options: &ReduceOptions) -> Result<Vec<u8>> {
+    match gpx_processing::input::decode_upload(data)? {
+        Upload::Gpx(gpx_string) => reduce_compress_gpx_with_options(&gpx_string, options),
+        Upload::Fit(bytes) => Ok(gpx_processing::pipeline::process_fit(&bytes, options, &AnalysisOptions::default())?.payload),
+    }
+}
+
+/// Decompresses a previously compressed GPX file, detecting the codec from its header.
+///
+/// This function reverses the compression step only, returning the stored
+/// text as-is; for reduced payloads that is the reduced JSON. Use
+/// [`decompress_gpx_to_xml`] to get a GPX document instead.
 ///
 /// # Arguments
 /// * `compressed_data` - The compressed binary data
 ///
 /// # Returns
-/// * `Result<String, JsValue>` - The decompressed GPX string or an error
+/// * `Result<String>` - The decompressed text or an error
+///
+/// # Errors
+/// Thrown in JavaScript as an `Error` whose `code` property is one of:
+/// * `DECOMPRESSION` if the payload cannot be decompressed
+/// * `INVALID_ENCODING` if the decompressed bytes are not UTF-8 text
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn decompress_gpx(compressed_data: &[u8]) -> Result<String> {
+    // Pick the decoder from the gzip magic bytes or the compression header
+    let decompressed_data = gpx_processing::compress::decompress_bytes(compressed_data)?;
+
+    String::from_utf8(decompressed_data).map_err(|e| GpxProcessorError::InvalidEncoding(e.to_string()))
+}
+
+/// Decodes a binary payload (`PayloadFormat::Binary`) into the reduced GPX for the map page.
+///
+/// # Arguments
+/// * `data` - The compressed binary payload, as stored
+///
+/// # Returns
+/// * `Result<JsValue>` - The reduced GPX as a plain JavaScript object, in the
+///   same shape as the JSON payload
+///
+/// # Errors
+/// * `Decompression`, `TooLarge`, `InvalidPayload` or `TooManyPoints` if the payload cannot be decoded
+#[cfg(feature = "wasm")]
 #[wasm_bindgen]
-pub fn decompress_gpx(compressed_data: &[u8]) -> Result<String, JsValue> {
-    // Create a gzip decoder for the compressed data
-    let mut decoder = GzDecoder::new(compressed_data);
-    let mut decompressed_data = String::new();
-    
-    // Read the decompressed data into a string
-    decoder
-        .read_to_string(&mut decompressed_data)
-        .map_err(|e| JsValue::from_str(&format!("Decompression error: {}", e)))?;
-    
-    Ok(decompressed_data)
+pub fn decode_smlr_binary(data: &[u8]) -> Result<JsValue> {
+    let reduced_gpx = gpx_processing::binary::decode_smlr_gpx(data)?;
+    Ok(reduced_gpx.serialize(&serde_wasm_bindgen::Serializer::json_compatible())?)
+}
+
+/// Decodes a Google encoded polyline into `[lat, lon]` or `[lat, lon, ele]` coordinates.
+///
+/// # Arguments
+/// * `encoded` - The encoded polyline
+/// * `precision` - Decimal places it was encoded with
+/// * `with_elevation` - Whether it carries elevation as a third dimension
+///
+/// # Returns
+/// * `Result<JsValue>` - An array of coordinate arrays
+///
+/// # Errors
+/// * `InvalidPayload` if the string is not a well-formed polyline
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+pub fn decode_polyline(encoded: &str, precision: PolylinePrecision, with_elevation: bool) -> Result<JsValue> {
+    let polylines = EncodedPolylines { tracks: vec![vec![encoded.to_string()]], routes: Vec::new() };
+    let decoded = gpx_processing::polyline::decode_polylines(&polylines, precision, with_elevation)?;
+    let coordinates: Vec<Vec<f64>> = decoded.trk.iter()
+        .flat_map(|track| &track.trkseg)
+        .flat_map(|segment| &segment.trkpt)
+        .map(|point| match point.ele {
+            Some(ele) => vec![point.lat, point.lon, ele],
+            None => vec![point.lat, point.lon],
+        })
+        .collect();
+    Ok(serde_wasm_bindgen::to_value(&coordinates)?)
+}
+
+/// Decodes a stored payload back into a GPX 1.1 XML document for download.
+///
+/// The payload format is detected automatically: reduced JSON (bare or in the
+/// legacy `<gpx>` wrapper), the binary layout or plain GPX, each optionally
+/// gzip-compressed. Reduced payloads lose only what reduction dropped.
+///
+/// # Arguments
+/// * `data` - The payload as stored
+///
+/// # Returns
+/// * `Result<String>` - A minified GPX 1.1 document that opens in Garmin, Strava and similar tools
+///
+/// # Errors
+/// * `Decompression`, `TooLarge`, `InvalidPayload`, `Serialization` or `ParseError` if the payload cannot be decoded
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn decompress_gpx_to_xml(data: &[u8]) -> Result<String> {
+    gpx_processing::payload::payload_to_gpx_xml(data)
 }
 
 /// Parses a GPX string into a structured Gpx object.
@@ -554,45 +663,18 @@ pub fn decompress_gpx(compressed_data: &[u8]) -> Result<String, JsValue> {
 /// * `gpx_string` - The raw GPX file content as a string
 ///
 /// # Returns
-/// * `Result<Gpx, String>` - The parsed GPX structure or an error message
-fn parse_gpx_from_string(gpx_string: &str) -> Result<Gpx, String> {
+/// * `Result<Gpx>` - The parsed GPX structure or a `ParseError`
+fn parse_gpx_from_string(gpx_string: &str) -> Result<Gpx> {
     // Use the gpx crate to parse the GPX XML
 
-    let gpx: Gpx = gpx::read(gpx_string.as_bytes()).map_err(|e| format!("Error parsing GPX: {}", e))?;
+    let gpx: Gpx = gpx::read(gpx_string.as_bytes())?;
     Ok(gpx)
 }
 
-fn write_gpx_from_parsed_gpx_string(parsed_gpx: Gpx) -> Result<String, String> {
+fn write_gpx_from_parsed_gpx_string(parsed_gpx: Gpx) -> Result<String> {
     let mut buffer = Vec::new();
     if let Err(e) = gpx::write(&parsed_gpx, &mut buffer) {
-        return Err(format!("Error writing GPX: {}", e));
+        return Err(GpxProcessorError::Serialization(format!("Error writing GPX: {}", e)));
     }
-    String::from_utf8(buffer).map_err(|e| format!("UTF-8 conversion error: {}", e))
+    String::from_utf8(buffer).map_err(|e| GpxProcessorError::InvalidEncoding(e.to_string()))
 }
-// #[wasm_bindgen]
-// pub fn log_gpx_size(data: &[u8]) -> JsValue {
-//     // Try to detect format
-//     let as_str = std::str::from_utf8(data).ok();
-//     let (size, format) = if let Some(s) = as_str {
-//         if s.trim_start().starts_with("<?xml") && s.contains("<gpx") {
-//             (data.len(), "Raw GPX XML")
-//         } else if s.contains("\"trk\"") && s.contains("\"trkseg\"") && s.contains("\"trkpt\"") {
-//             (data.len(), "Reduced GPX JSON")
-//         } else {
-//             (data.len(), "Unknown text format")
-//         }
-//     } else {
-//         (data.len(), "Compressed GPX binary")
-//     };
-
-//     // Build result object
-//     let result = js_sys::Object::new();
-//     js_sys::Reflect::set(&result, &JsValue::from_str("size_bytes"), &JsValue::from_f64(size as f64)).unwrap();
-//     js_sys::Reflect::set(&result, &JsValue::from_str("format"), &JsValue::from_str(format)).unwrap();
-
-//     // // Log to browser console
-//     // console::log_1(&JsValue::from_str(&format!("GPX format: {}, size: {} bytes", format, size)));
-//     // console::log_1(&result);
-
-//     result.into()
-// }
\ No newline at end of file
@@ -0,0 +1,20 @@
+//! Timing Module
+//!
+//! Wall-clock milliseconds for the performance metrics in `GpxAnalysis`.
+//! The browser build reads `Date.now()`; native builds use the system clock,
+//! since `std::time` is unavailable on `wasm32-unknown-unknown`.
+
+/// Current time in milliseconds since the Unix epoch.
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub fn now_ms() -> f64 {
+    js_sys::Date::now()
+}
+
+/// Current time in milliseconds since the Unix epoch.
+#[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+pub fn now_ms() -> f64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs_f64() * 1000.0)
+        .unwrap_or(0.0)
+}
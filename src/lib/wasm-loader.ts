@@ -81,13 +81,50 @@ export async function loadWasmModule() {
 }
 
 
+/**
+ * Wraps an error thrown by the WASM module in a user-facing Error,
+ * keeping the machine-readable `code` and `details` set by the Rust side
+ * (e.g. `TOO_LARGE`, `TOO_MANY_POINTS`, `PARSE_ERROR`, `NOT_GPX`).
+ * @param error The error thrown by the WASM module
+ * @param message The user-facing message
+ * @returns An Error carrying the original code and details, when present
+ */
+function toGpxProcessingError(error: unknown, message: string) {
+  const wrapped = new Error(message) as Error & { code?: string; details?: unknown };
+  if (error && typeof error === 'object' && 'code' in error) {
+    wrapped.code = (error as { code?: string }).code;
+    wrapped.details = (error as { details?: unknown }).details;
+  }
+  return wrapped;
+}
+
+/**
+ * Ride statistics from the analysis returned by the WASM module.
+ * Times and speeds are null when the file has no timestamps.
+ */
+export interface RideStats {
+  total_distance_m: number;
+  track_distances_m: number[];
+  elapsed_time_s: number | null;
+  moving_time_s: number | null;
+  average_moving_speed_mps: number | null;
+  max_speed_mps: number | null;
+}
+
+/**
+ * Result of `process_gpx_with_analytics`: the analysis plus the compressed payload it describes
+ */
+export interface ProcessedGpx {
+  analysis: RideStats & Record<string, unknown>;
+  data: Uint8Array;
+}
+
 /**
  * Safely processes a GPX string using the WASM module
  * @param gpxString The GPX data to process
- * @returns The processed GPX data
+ * @returns The analysis and the compressed payload ready for upload
  */
-
-export async function processGpx(gpxString: string) {
+export async function processGpx(gpxString: string): Promise<ProcessedGpx> {
   const module = await loadWasmModule();
   if (!module) throw new Error('WASM module not loaded');
 
@@ -99,7 +136,7 @@ export async function processGpx(gpxString: string) {
     }
   } catch (error) {
     // console.error('Error processing GPX data:', error);
-    throw new Error('Failed to process GPX data. Please check file format and try again.');
+    throw toGpxProcessingError(error, 'Failed to process GPX data. Please check file format and try again.');
   }
 }
 
@@ -120,7 +157,47 @@ export async function reduceCompressGpx(gpxString: string) {
     }
   } catch (error) {
     console.error('Error compressing GPX data:', error);
-    throw new Error('Failed to compress GPX data. Please check file format and try again.');
+    throw toGpxProcessingError(error, 'Failed to compress GPX data. Please check file format and try again.');
+  }
+}
+
+/**
+ * Safely decodes a binary (`SMLR`) route payload using the WASM module
+ * @param data The gzipped binary payload as stored
+ * @returns The reduced GPX object, shaped like the JSON payload
+ */
+export async function decodeSmlrBinary(data: Uint8Array) {
+  const module = await loadWasmModule();
+  if (!module) throw new Error('WASM module not loaded');
+
+  try {
+    if (typeof module.decode_smlr_binary === 'function') {
+      return module.decode_smlr_binary(data);
+    } else {
+      throw new Error('decode_smlr_binary function not found in WASM module');
+    }
+  } catch (error) {
+    throw toGpxProcessingError(error, 'Failed to decode route data.');
+  }
+}
+
+/**
+ * Safely rebuilds a downloadable GPX 1.1 document from a stored payload using the WASM module
+ * @param data The payload as stored (reduced JSON, binary or GPX, optionally gzipped)
+ * @returns The GPX XML string
+ */
+export async function decompressGpxToXml(data: Uint8Array) {
+  const module = await loadWasmModule();
+  if (!module) throw new Error('WASM module not loaded');
+
+  try {
+    if (typeof module.decompress_gpx_to_xml === 'function') {
+      return module.decompress_gpx_to_xml(data);
+    } else {
+      throw new Error('decompress_gpx_to_xml function not found in WASM module');
+    }
+  } catch (error) {
+    throw toGpxProcessingError(error, 'Failed to rebuild GPX file from stored route.');
   }
 }
 